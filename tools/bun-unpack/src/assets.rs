@@ -0,0 +1,344 @@
+//! Identify what a module's contents are: native addons (ELF / PE / Mach-O),
+//! WebAssembly and common asset formats.
+//!
+//! **Interaction**: `ModuleEntry::from_module` records the result as `asset`
+//! in `manifest.json`; `unpack` notes it in `manifest.txt` and `extract
+//! --assets-only` selects modules with `is_asset_with` on that entry, so
+//! nothing is sniffed twice. Executables are parsed with
+//! goblin for their architecture and exported symbols (for a `.node` addon,
+//! e.g. `napi_register_module_v1`); everything else is recognized by magic bytes.
+
+use goblin::elf::header::{EM_386, EM_AARCH64, EM_ARM, EM_RISCV, EM_X86_64};
+use goblin::elf::section_header::{SHN_ABS, SHN_UNDEF};
+use goblin::elf::sym::{STB_GLOBAL, STB_WEAK, STT_FUNC, STT_OBJECT};
+use goblin::mach::{Mach, MachO, SingleArch};
+use goblin::pe::header::{COFF_MACHINE_ARM64, COFF_MACHINE_X86, COFF_MACHINE_X86_64};
+use goblin::Object;
+use serde::{Deserialize, Serialize};
+
+use crate::extract::macho_arch_name;
+use crate::graph::Module;
+use crate::kind::Loader;
+
+/// Detected type of a module's contents.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetInfo {
+    /// `elf`, `pe`, `macho`, `wasm`, `png`, ... (see [`MAGIC`]).
+    pub kind: String,
+    /// Architectures of a native binary (several for a universal Mach-O).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arch: Vec<String>,
+    /// Exported symbols of a native binary, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exports: Vec<String>,
+}
+
+/// Magic bytes at offset 0 (or `offset`) and the kind they identify.
+pub const MAGIC: &[(&str, usize, &[u8])] = &[
+    ("wasm", 0, b"\0asm"),
+    ("png", 0, b"\x89PNG\r\n\x1a\n"),
+    ("jpeg", 0, b"\xff\xd8\xff"),
+    ("gif", 0, b"GIF8"),
+    ("webp", 8, b"WEBP"),
+    ("pdf", 0, b"%PDF-"),
+    ("zip", 0, b"PK\x03\x04"),
+    ("gzip", 0, b"\x1f\x8b"),
+    ("zstd", 0, b"\x28\xb5\x2f\xfd"),
+    ("sqlite", 0, b"SQLite format 3\0"),
+    ("woff", 0, b"wOFF"),
+    ("woff2", 0, b"wOF2"),
+    ("otf", 0, b"OTTO"),
+    ("ttf", 0, b"\0\x01\0\0"),
+];
+
+/// Identifies `contents`; `None` for text / JavaScript and unknown data.
+pub fn sniff_asset(contents: &[u8]) -> Option<AssetInfo> {
+    if let Some(info) = sniff_native(contents) {
+        return Some(info);
+    }
+    let (kind, _, _) = MAGIC
+        .iter()
+        .find(|(_, offset, magic)| contents.get(*offset..).is_some_and(|c| c.starts_with(magic)))?;
+    Some(AssetInfo {
+        kind: kind.to_string(),
+        arch: Vec::new(),
+        exports: Vec::new(),
+    })
+}
+
+/// Whether `module` is an asset rather than code: loaded by a non-JS loader
+/// (`file`, `napi`, `wasm`, ...) or holding a recognized binary format.
+pub fn is_asset(module: &Module<'_>) -> bool {
+    is_asset_with(module, sniff_asset(module.contents).as_ref())
+}
+
+/// Like `is_asset`, with `asset` already sniffed from `module.contents`
+/// (e.g. the `asset` of its manifest entry).
+pub fn is_asset_with(module: &Module<'_>, asset: Option<&AssetInfo>) -> bool {
+    let code = module.loader.is_javascript()
+        || matches!(module.loader, Loader::Json | Loader::Jsonc | Loader::Toml | Loader::Yaml | Loader::Css);
+    !code || asset.is_some()
+}
+
+fn sniff_native(contents: &[u8]) -> Option<AssetInfo> {
+    let native = |kind: &str, arch: Vec<String>, mut exports: Vec<String>| {
+        exports.sort();
+        exports.dedup();
+        Some(AssetInfo {
+            kind: kind.to_string(),
+            arch,
+            exports,
+        })
+    };
+    match Object::parse(contents).ok()? {
+        Object::Elf(elf) => {
+            let exports = elf
+                .dynsyms
+                .iter()
+                .filter(|s| {
+                    matches!(s.st_bind(), STB_GLOBAL | STB_WEAK)
+                        && matches!(s.st_type(), STT_FUNC | STT_OBJECT)
+                        && !matches!(s.st_shndx as u32, SHN_UNDEF | SHN_ABS)
+                })
+                .filter_map(|s| elf.dynstrtab.get_at(s.st_name))
+                .map(str::to_string)
+                .collect();
+            native("elf", vec![elf_arch_name(elf.header.e_machine)], exports)
+        }
+        Object::PE(pe) => {
+            let exports = pe.exports.iter().filter_map(|e| e.name).map(str::to_string).collect();
+            native("pe", vec![pe_arch_name(pe.header.coff_header.machine)], exports)
+        }
+        Object::Mach(Mach::Binary(macho)) => {
+            let arch = macho_arch_name(macho.header.cputype, macho.header.cpusubtype);
+            native("macho", vec![arch], macho_exports(&macho))
+        }
+        Object::Mach(Mach::Fat(multi)) => {
+            let mut arch = Vec::new();
+            let mut exports = Vec::new();
+            for i in 0..multi.narches {
+                if let Ok(SingleArch::MachO(macho)) = multi.get(i) {
+                    arch.push(macho_arch_name(macho.header.cputype, macho.header.cpusubtype));
+                    exports.extend(macho_exports(&macho));
+                }
+            }
+            native("macho", arch, exports)
+        }
+        _ => None,
+    }
+}
+
+fn macho_exports(macho: &MachO<'_>) -> Vec<String> {
+    macho.exports().unwrap_or_default().into_iter().map(|e| e.name).collect()
+}
+
+/// Architecture name in the Mach-O spelling (`x86_64`, `arm64`) for ELF `e_machine`.
+fn elf_arch_name(machine: u16) -> String {
+    match machine {
+        EM_X86_64 => "x86_64".to_string(),
+        EM_AARCH64 => "arm64".to_string(),
+        EM_386 => "x86".to_string(),
+        EM_ARM => "arm".to_string(),
+        EM_RISCV => "riscv".to_string(),
+        other => goblin::elf::header::machine_to_str(other).to_lowercase(),
+    }
+}
+
+/// Architecture name in the Mach-O spelling for a COFF machine type.
+fn pe_arch_name(machine: u16) -> String {
+    match machine {
+        COFF_MACHINE_X86_64 => "x86_64".to_string(),
+        COFF_MACHINE_ARM64 => "arm64".to_string(),
+        COFF_MACHINE_X86 => "x86".to_string(),
+        other => goblin::pe::header::machine_to_str(other).to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::graph::ModuleGraph;
+    use crate::inject::tests::{synthetic_macho, synthetic_pe};
+    use crate::layout::LAYOUTS;
+
+    /// Minimal x86_64 ELF shared object whose `.dynsym` holds `exports` as
+    /// defined functions, plus an undefined import and a local symbol. goblin
+    /// takes the symbol count from `DT_HASH`'s `nchain`.
+    fn synthetic_elf(exports: &[&str]) -> Vec<u8> {
+        const SYMTAB: usize = 64 + 2 * 56;
+        let mut names = vec![0u8];
+        let mut syms = vec![0u8; 24];
+        let mut sym = |names: &mut Vec<u8>, name: &str, info: u8, shndx: u16| {
+            let mut s = [0u8; 24];
+            s[..4].copy_from_slice(&(names.len() as u32).to_le_bytes());
+            s[4] = info;
+            s[6..8].copy_from_slice(&shndx.to_le_bytes());
+            s[8..16].copy_from_slice(&0x100u64.to_le_bytes());
+            syms.extend_from_slice(&s);
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        };
+        for name in exports {
+            sym(&mut names, name, (STB_GLOBAL << 4) | STT_FUNC, 1);
+        }
+        sym(&mut names, "puts", (STB_GLOBAL << 4) | STT_FUNC, SHN_UNDEF as u16);
+        sym(&mut names, "helper", STT_FUNC, 1);
+        let strtab = SYMTAB + syms.len();
+        let hash = (strtab + names.len()).next_multiple_of(4);
+        let nchain = (syms.len() / 24) as u32;
+        let dynamic = (hash + 12 + 4 * nchain as usize).next_multiple_of(8);
+        let entries: [(u64, u64); 6] = [
+            (4, hash as u64),
+            (6, SYMTAB as u64),
+            (5, strtab as u64),
+            (10, names.len() as u64),
+            (11, 24),
+            (0, 0),
+        ];
+        let total = dynamic + entries.len() * 16;
+
+        let mut elf = vec![0u8; SYMTAB];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[4] = 2;
+        elf[5] = 1;
+        elf[6] = 1;
+        elf[16..18].copy_from_slice(&3u16.to_le_bytes());
+        elf[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+        elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+        elf[32..40].copy_from_slice(&64u64.to_le_bytes());
+        elf[52..54].copy_from_slice(&64u16.to_le_bytes());
+        elf[54..56].copy_from_slice(&56u16.to_le_bytes());
+        elf[56..58].copy_from_slice(&2u16.to_le_bytes());
+        elf[58..60].copy_from_slice(&64u16.to_le_bytes());
+        // PT_LOAD mapping the whole file at address 0, then PT_DYNAMIC.
+        for (i, (kind, offset, size)) in [(1u32, 0, total), (2, dynamic, total - dynamic)].into_iter().enumerate() {
+            let ph = 64 + i * 56;
+            elf[ph..ph + 4].copy_from_slice(&kind.to_le_bytes());
+            elf[ph + 4..ph + 8].copy_from_slice(&4u32.to_le_bytes());
+            for field in [8, 16, 24] {
+                elf[ph + field..ph + field + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+            }
+            elf[ph + 32..ph + 40].copy_from_slice(&(size as u64).to_le_bytes());
+            elf[ph + 40..ph + 48].copy_from_slice(&(size as u64).to_le_bytes());
+            elf[ph + 48..ph + 56].copy_from_slice(&8u64.to_le_bytes());
+        }
+        elf.extend_from_slice(&syms);
+        elf.extend_from_slice(&names);
+        elf.resize(hash, 0);
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        elf.extend_from_slice(&nchain.to_le_bytes());
+        elf.resize(dynamic, 0);
+        for (tag, value) in entries {
+            elf.extend_from_slice(&tag.to_le_bytes());
+            elf.extend_from_slice(&value.to_le_bytes());
+        }
+        elf
+    }
+
+    /// `synthetic_pe` with an export directory in `.text` naming `export`.
+    fn pe_exporting(export: &str) -> Vec<u8> {
+        const OPT: usize = 0x58;
+        const TEXT: usize = 0x200;
+        const RVA: u32 = 0x1000;
+        let mut pe = synthetic_pe(None);
+        let u32_at = |pe: &mut Vec<u8>, at: usize, v: u32| pe[at..at + 4].copy_from_slice(&v.to_le_bytes());
+        // Export data directory, and a `.text` virtual size covering it.
+        u32_at(&mut pe, OPT + 112, RVA + 0x10);
+        u32_at(&mut pe, OPT + 116, 40);
+        u32_at(&mut pe, OPT + 240 + 8, 0x100);
+        let dir = TEXT + 0x10;
+        u32_at(&mut pe, dir + 12, RVA + 0x70);
+        u32_at(&mut pe, dir + 16, 1);
+        u32_at(&mut pe, dir + 20, 1);
+        u32_at(&mut pe, dir + 24, 1);
+        u32_at(&mut pe, dir + 28, RVA + 0x40);
+        u32_at(&mut pe, dir + 32, RVA + 0x44);
+        u32_at(&mut pe, dir + 36, RVA + 0x48);
+        u32_at(&mut pe, TEXT + 0x40, RVA);
+        u32_at(&mut pe, TEXT + 0x44, RVA + 0x50);
+        pe[TEXT + 0x50..TEXT + 0x50 + export.len()].copy_from_slice(export.as_bytes());
+        pe[TEXT + 0x70..TEXT + 0x7a].copy_from_slice(b"addon.node");
+        pe
+    }
+
+    /// `synthetic_macho` with an `LC_DYLD_EXPORTS_TRIE` exporting `export`.
+    fn macho_exporting(export: &str) -> Vec<u8> {
+        let mut macho = synthetic_macho(b"", 0);
+        let u32_at = |m: &mut Vec<u8>, at: usize, v: u32| m[at..at + 4].copy_from_slice(&v.to_le_bytes());
+        // Root node with one edge, then a terminal node at address 0.
+        let mut trie = vec![0, 1];
+        trie.extend_from_slice(export.as_bytes());
+        trie.push(0);
+        trie.push(trie.len() as u8 + 1);
+        trie.extend_from_slice(&[2, 0, 0, 0]);
+        let command = 32 + 72 + 80;
+        let trie_at = macho.len();
+        u32_at(&mut macho, 16, 2);
+        u32_at(&mut macho, 20, 72 + 80 + 16);
+        u32_at(&mut macho, command, 0x8000_0033);
+        u32_at(&mut macho, command + 4, 16);
+        u32_at(&mut macho, command + 8, trie_at as u32);
+        u32_at(&mut macho, command + 12, trie.len() as u32);
+        macho.extend_from_slice(&trie);
+        macho
+    }
+
+    #[test]
+    fn elf_addon_has_arch_and_defined_exports() {
+        let elf = synthetic_elf(&["napi_register_module_v1", "node_api_module_get_api_version_v1"]);
+        let info = sniff_asset(&elf).unwrap();
+        assert_eq!(info.kind, "elf");
+        assert_eq!(info.arch, ["x86_64"]);
+        // The undefined `puts` and the local `helper` are not exports.
+        assert_eq!(info.exports, ["napi_register_module_v1", "node_api_module_get_api_version_v1"]);
+    }
+
+    #[test]
+    fn pe_addon_has_arch_and_exports() {
+        let pe = sniff_asset(&pe_exporting("napi_register_module_v1")).unwrap();
+        assert_eq!(pe.kind, "pe");
+        assert_eq!(pe.arch, ["x86_64"]);
+        assert_eq!(pe.exports, ["napi_register_module_v1"]);
+        assert!(sniff_asset(&synthetic_pe(None)).unwrap().exports.is_empty());
+    }
+
+    #[test]
+    fn macho_addon_has_arch_and_exports() {
+        let macho = sniff_asset(&macho_exporting("_napi_register_module_v1")).unwrap();
+        assert_eq!(macho.kind, "macho");
+        assert_eq!(macho.arch, ["x86_64"]);
+        assert_eq!(macho.exports, ["_napi_register_module_v1"]);
+    }
+
+    #[test]
+    fn magic_bytes() {
+        let kind = |contents: &[u8]| sniff_asset(contents).map(|info| info.kind);
+        assert_eq!(kind(b"\0asm\x01\0\0\0").as_deref(), Some("wasm"));
+        assert_eq!(kind(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").as_deref(), Some("png"));
+        assert_eq!(kind(b"RIFF\x10\0\0\0WEBPVP8 ").as_deref(), Some("webp"));
+        let wasm = sniff_asset(b"\0asm\x01\0\0\0").unwrap();
+        assert!(wasm.arch.is_empty() && wasm.exports.is_empty());
+    }
+
+    #[test]
+    fn asset_from_loader_or_sniffed_contents() {
+        let blob = sample_blob(&LAYOUTS[0]);
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let js = &graph.modules[0];
+        assert!(!is_asset(js));
+        assert!(!is_asset_with(js, None));
+        assert!(is_asset_with(js, sniff_asset(b"\0asm\x01\0\0\0").as_ref()));
+        let mut file = *js;
+        file.loader = Loader::File;
+        assert!(is_asset_with(&file, None));
+    }
+
+    #[test]
+    fn code_is_not_an_asset() {
+        assert_eq!(sniff_asset(b"console.log(1);\n"), None);
+        assert_eq!(sniff_asset(b""), None);
+        // Too short for the magic at offset 8.
+        assert_eq!(sniff_asset(b"RIFF"), None);
+    }
+}
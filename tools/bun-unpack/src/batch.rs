@@ -0,0 +1,403 @@
+//! Unpack many executables at once: every file under a directory, or every
+//! path matching a glob.
+//!
+//! **Interaction**: Used by the CLI `batch` subcommand. `collect_inputs`
+//! expands the argument; `unpack_batch` unpacks each file on a rayon pool into
+//! its own directory, like the default mode with `--all-arches` (one
+//! subdirectory per architecture of a universal Mach-O), with the layout hinted
+//! by the detected runtime, or into a `Store` as build `<path>[/<arch>]`. A
+//! file that fails (or panics the parser) becomes a `failed` entry with no
+//! module counts instead of stopping the batch; files that are not
+//! executables are `skipped`. The
+//! output directory is left out of the walk, so a rerun with it inside the
+//! input directory does not pick up extracted `.node` files.
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::Glob;
+use goblin::Hint;
+use rayon::prelude::*;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::extract::{locate_all_bun_blobs, map_file};
+use crate::manifest::SourceInfo;
+use crate::runtime::{parse_for_runtime, RuntimeInfo};
+use crate::store::Store;
+use crate::unpack::{write_modules, UnpackOptions};
+
+/// Report written to the batch output directory.
+pub const BATCH_REPORT: &str = "batch.json";
+
+/// Characters that make an input argument a glob rather than a path.
+const GLOB_CHARS: [char; 4] = ['*', '?', '[', '{'];
+
+/// Outcome for one input file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Ok,
+    Failed,
+    /// Not a PE / ELF / Mach-O file.
+    Skipped,
+}
+
+impl BatchStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+/// One input file of a batch.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchEntry {
+    pub file: String,
+    /// Output directory relative to the batch output directory, or the build
+    /// name in a store.
+    pub output: String,
+    pub status: BatchStatus,
+    /// Modules unpacked, summed over architectures; 0 if the file failed.
+    pub modules: usize,
+    /// Architectures of a universal Mach-O, each unpacked to `<output>/<arch>`;
+    /// empty if the file failed (a warning names those written before the failure).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub arches: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Why the file failed or was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of `unpack_batch`, in input order.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchReport {
+    pub ok: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub entries: Vec<BatchEntry>,
+}
+
+/// Expands `input`: a file, a directory (walked recursively) or a glob such as
+/// `releases/**/*.exe`. Returns the root that output paths are made relative
+/// to, and the files sorted by path. The directory `skip` (the batch output) is
+/// not walked.
+pub fn collect_inputs(input: &str, skip: Option<&Path>) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let skip = skip.and_then(|dir| fs::canonicalize(dir).ok());
+    let walk_files = |root: &Path, keep: &dyn Fn(&Path) -> bool| walk_files(root, skip.as_deref(), keep);
+    let path = Path::new(input);
+    if path.is_file() {
+        let root = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        return Ok((root, vec![path.to_path_buf()]));
+    }
+    if path.is_dir() {
+        let files = walk_files(path, &|_| true)?;
+        return Ok((path.to_path_buf(), files));
+    }
+    if !input.contains(GLOB_CHARS) {
+        anyhow::bail!("{:?} does not exist", input);
+    }
+    // Walk from the longest leading part without glob characters.
+    let root: PathBuf = path
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(GLOB_CHARS))
+        .collect();
+    let matcher = Glob::new(input)
+        .with_context(|| format!("invalid glob {:?}", input))?
+        .compile_matcher();
+    let files = if root.as_os_str().is_empty() {
+        // Walking "." yields "./a/b"; match "a/b" against the pattern.
+        walk_files(Path::new("."), &|p| matcher.is_match(p.strip_prefix(".").unwrap_or(p)))?
+    } else {
+        walk_files(&root, &|p| matcher.is_match(p))?
+    };
+    Ok((root, files))
+}
+
+/// Files under `root` that `keep` accepts, not descending into `skip`
+/// (canonical).
+fn walk_files(root: &Path, skip: Option<&Path>, keep: &dyn Fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let walk = WalkDir::new(root).sort_by_file_name().into_iter().filter_entry(|e| {
+        !e.file_type().is_dir() || skip.is_none_or(|skip| fs::canonicalize(e.path()).is_ok_and(|p| p != skip))
+    });
+    for entry in walk {
+        let entry = entry.with_context(|| format!("walk {:?}", root))?;
+        if entry.file_type().is_file() && keep(entry.path()) {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// Unpacks each of `files` to `out_dir/<path relative to root>/` with `jobs`
+/// workers (`None`: one per CPU), then writes the report to `out_dir/batch.json`.
+/// With `store`, graphs are added to it under the relative path instead (and
+/// `options` is unused).
+pub fn unpack_batch(
+    root: &Path,
+    files: &[PathBuf],
+    out_dir: &Path,
+    store: Option<&Store>,
+    options: &UnpackOptions,
+    jobs: Option<usize>,
+) -> Result<BatchReport> {
+    fs::create_dir_all(out_dir).with_context(|| format!("create out dir {:?}", out_dir))?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+        .context("start worker pool")?;
+    let entries: Vec<BatchEntry> =
+        pool.install(|| files.par_iter().map(|file| unpack_one(root, file, out_dir, store, options)).collect());
+
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+    let report = BatchReport {
+        ok: count(BatchStatus::Ok),
+        failed: count(BatchStatus::Failed),
+        skipped: count(BatchStatus::Skipped),
+        entries,
+    };
+    let path = out_dir.join(BATCH_REPORT);
+    let json = serde_json::to_vec_pretty(&report).context("serialize batch report")?;
+    fs::write(&path, json).with_context(|| format!("write {:?}", path))?;
+    Ok(report)
+}
+
+fn unpack_one(
+    root: &Path,
+    file: &Path,
+    out_dir: &Path,
+    store: Option<&Store>,
+    options: &UnpackOptions,
+) -> BatchEntry {
+    let rel = file.strip_prefix(root).unwrap_or(file);
+    let output = rel.display().to_string();
+    let mut entry = BatchEntry {
+        file: file.display().to_string(),
+        output: match store {
+            Some(_) => output.replace('\\', "/"),
+            None => output,
+        },
+        status: BatchStatus::Ok,
+        modules: 0,
+        arches: Vec::new(),
+        runtime: None,
+        warnings: Vec::new(),
+        error: None,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        unpack_file(file, &out_dir.join(rel), store, options, &mut entry)
+    }));
+    let error = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(format!("{:#}", e)),
+        Err(payload) => Some(format!("panicked: {}", panic_message(payload.as_ref()))),
+    };
+    if let Some(error) = error {
+        // A universal binary may fail after some architectures were written;
+        // counts are only reported for files that unpacked completely.
+        if !entry.arches.is_empty() {
+            entry
+                .warnings
+                .push(format!("{} written before the failure", entry.arches.join(", ")));
+        }
+        entry.status = BatchStatus::Failed;
+        entry.error = Some(error);
+        entry.modules = 0;
+        entry.arches.clear();
+    }
+    entry
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Unpacks every graph in `file` into `dir` (or `store`), filling in `entry`.
+fn unpack_file(
+    file: &Path,
+    dir: &Path,
+    store: Option<&Store>,
+    options: &UnpackOptions,
+    entry: &mut BatchEntry,
+) -> Result<()> {
+    let map = map_file(file)?;
+    let header: Option<&[u8; 16]> = map.get(..16).and_then(|h| h.try_into().ok());
+    let executable = header
+        .and_then(|h| goblin::peek_bytes(h).ok())
+        .is_some_and(|hint| matches!(hint, Hint::Elf(_) | Hint::Mach(_) | Hint::MachFat(_) | Hint::PE));
+    if !executable {
+        entry.status = BatchStatus::Skipped;
+        entry.error = Some("not a PE / ELF / Mach-O executable".to_string());
+        return Ok(());
+    }
+
+    for loc in locate_all_bun_blobs(&map)? {
+        let source = SourceInfo::from_location(file, &map, &loc);
+        let parsed = parse_for_runtime(&map[loc.range.clone()], source.runtime.as_ref());
+        let (graph, warnings) = match &loc.arch {
+            Some(arch) => parsed.with_context(|| format!("arch {}", arch))?,
+            None => parsed?,
+        };
+        entry.warnings.extend(warnings);
+        if entry.runtime.is_none() {
+            entry.runtime = source.runtime.clone();
+        }
+        match store {
+            Some(store) => {
+                let name = match &loc.arch {
+                    Some(arch) => format!("{}/{}", entry.output, arch),
+                    None => entry.output.clone(),
+                };
+                store.add_graph(&name, &graph, source)?;
+            }
+            None => {
+                let out = match &loc.arch {
+                    Some(arch) => dir.join(arch),
+                    None => dir.to_path_buf(),
+                };
+                write_modules(&graph, &out, source, options, |_| true)?;
+            }
+        }
+        entry.modules += graph.modules.len();
+        entry.arches.extend(loc.arch);
+    }
+    Ok(())
+}
+
+/// Formats `report` as one row per file (status, modules, Bun version, path),
+/// errors indented below, then a summary line.
+pub fn format_batch_report(report: &BatchReport) -> Vec<String> {
+    let mut lines = Vec::with_capacity(report.entries.len() + 2);
+    lines.push(format!("{:<8} {:>7}  {:<12} {}", "status", "modules", "bun", "file"));
+    for e in &report.entries {
+        let version = e.runtime.as_ref().map_or("-", |r| r.version.as_str());
+        lines.push(format!("{:<8} {:>7}  {:<12} {}", e.status.as_str(), e.modules, version, e.file));
+        for w in &e.warnings {
+            lines.push(format!("    warning: {}", w));
+        }
+        if let (BatchStatus::Failed, Some(error)) = (e.status, &e.error) {
+            lines.push(format!("    ! {}", error));
+        }
+    }
+    lines.push(format!(
+        "{} files: {} ok, {} failed, {} skipped",
+        report.entries.len(),
+        report.ok,
+        report.failed,
+        report.skipped
+    ));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::graph::BUN_TRAILER;
+    use crate::inject::tests::{synthetic_macho, synthetic_pe};
+    use crate::layout::Layout;
+
+    #[test]
+    fn collect_inputs_skips_output_dir() {
+        let dir = std::env::temp_dir().join(format!("bun-unpack-batch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("out/app")).unwrap();
+        fs::write(dir.join("app.exe"), b"MZ").unwrap();
+        fs::write(dir.join("out/app/addon.node"), b"\x7fELF").unwrap();
+
+        let input = dir.to_str().unwrap();
+        let (_, files) = collect_inputs(input, None).unwrap();
+        assert_eq!(files.len(), 2);
+        let (root, files) = collect_inputs(input, Some(&dir.join("out"))).unwrap();
+        assert_eq!(root, dir);
+        assert_eq!(files, [dir.join("app.exe")]);
+        let (_, files) = collect_inputs(&format!("{}/**/*", input), Some(&dir.join("out"))).unwrap();
+        assert_eq!(files, [dir.join("app.exe")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Universal Mach-O with an x86_64 slice holding `good` and an arm64 slice holding `bad`.
+    fn universal(good: &[u8], bad: &[u8]) -> Vec<u8> {
+        const SLICE: usize = 0x1000;
+        let mut exe = vec![0u8; SLICE];
+        exe[..4].copy_from_slice(&0xcafe_babeu32.to_be_bytes());
+        exe[4..8].copy_from_slice(&2u32.to_be_bytes());
+        for (i, (cputype, subtype, blob)) in [(0x0100_0007u32, 3u32, good), (0x0100_000c, 0, bad)].into_iter().enumerate() {
+            let mut slice = synthetic_macho(blob, (blob.len() + 8).next_multiple_of(8));
+            slice[4..8].copy_from_slice(&cputype.to_le_bytes());
+            slice[8..12].copy_from_slice(&subtype.to_le_bytes());
+            let offset = exe.len();
+            let arch = 8 + i * 20;
+            for (at, value) in [cputype, subtype, offset as u32, slice.len() as u32, 12].into_iter().enumerate() {
+                exe[arch + at * 4..arch + at * 4 + 4].copy_from_slice(&value.to_be_bytes());
+            }
+            exe.extend_from_slice(&slice);
+            exe.resize(exe.len().next_multiple_of(SLICE), 0);
+        }
+        exe
+    }
+
+    #[test]
+    fn unpack_batch_reports_every_file() {
+        let dir = std::env::temp_dir().join(format!("bun-unpack-batch-e2e-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let input = dir.join("in");
+        fs::create_dir_all(&input).unwrap();
+        let blob = sample_blob(Layout::current());
+        fs::write(input.join("broken.exe"), synthetic_pe(Some(b"not a module graph"))).unwrap();
+        fs::write(input.join("good.exe"), synthetic_pe(Some(&blob))).unwrap();
+        fs::write(input.join("notes.txt"), "hello").unwrap();
+        // Found by its trailer, but does not parse.
+        let bad = [b"not a module graph".as_slice(), BUN_TRAILER].concat();
+        fs::write(input.join("universal"), universal(&blob, &bad)).unwrap();
+
+        let out = dir.join("out");
+        let (root, files) = collect_inputs(input.to_str().unwrap(), Some(&out)).unwrap();
+        let report = unpack_batch(&root, &files, &out, None, &UnpackOptions::default(), Some(2)).unwrap();
+        assert_eq!((report.ok, report.failed, report.skipped), (1, 2, 1));
+        assert!(out.join("good.exe/root/cli.js").is_file());
+
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(out.join(BATCH_REPORT)).unwrap()).unwrap();
+        assert_eq!((&json["ok"], &json["failed"], &json["skipped"]), (&1.into(), &2.into(), &1.into()));
+        let entries = json["entries"].as_array().unwrap();
+        let row = |e: &serde_json::Value| (e["output"].as_str().unwrap().to_string(), e["status"].clone(), e["modules"].clone());
+        assert_eq!(
+            entries.iter().map(row).collect::<Vec<_>>(),
+            [
+                ("broken.exe".to_string(), "failed".into(), 0.into()),
+                ("good.exe".to_string(), "ok".into(), 2.into()),
+                ("notes.txt".to_string(), "skipped".into(), 0.into()),
+                ("universal".to_string(), "failed".into(), 0.into()),
+            ]
+        );
+        // The x86_64 slice was written before arm64 failed; only a warning says so.
+        let universal = &entries[3];
+        assert!(universal.get("arches").is_none());
+        assert_eq!(universal["warnings"], serde_json::json!(["x86_64 written before the failure"]));
+        assert!(universal["error"].as_str().unwrap().starts_with("arch arm64"), "{}", universal["error"]);
+        assert!(out.join("universal/x86_64/root/cli.js").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn panic_messages() {
+        let payload = panic::catch_unwind(|| panic!("bad offset {}", 3)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "bad offset 3");
+        let payload = panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static");
+    }
+}
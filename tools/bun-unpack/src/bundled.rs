@@ -0,0 +1,280 @@
+//! Split bundled JavaScript back into the original files it was built from.
+//!
+//! **Interaction**: Called by `unpack::write_modules` when
+//! `UnpackOptions::bundled` is set; the returned entries go into
+//! `manifest.json` as `bundled`. Bun's bundler starts each original file with a
+//! `// <path>` comment at column 0 after a blank line, and wraps CommonJS files
+//! in `__commonJS(...)` and lazily initialized ESM in `__esm(...)`, each
+//! assigned by a top-level `var`. Minified bundles drop the comments, so
+//! nothing is split there. Segments are written as they appear in the bundle
+//! (wrappers included), deduplicated like `sources/`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{Module, ModuleGraph};
+use crate::manifest::sha256_hex;
+use crate::sources::{free_path, normalize_source_path};
+
+/// Directory under the output directory that split files are written to.
+pub const BUNDLED_DIR: &str = "bundled";
+
+/// Extensions a boundary comment's path must end with.
+const SOURCE_EXTENSIONS: [&str; 13] = [
+    "js", "mjs", "cjs", "jsx", "ts", "mts", "cts", "tsx", "json", "jsonc", "toml", "yaml", "txt",
+];
+
+/// How Bun emitted one original file inside the bundle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrapper {
+    /// `var require_x = __commonJS(...)`.
+    Cjs,
+    /// `var init_x = __esm(...)`.
+    Esm,
+    /// Scope-hoisted into the bundle's top level.
+    None,
+}
+
+impl Wrapper {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cjs => "cjs",
+            Self::Esm => "esm",
+            Self::None => "none",
+        }
+    }
+}
+
+/// One original file found in a bundled module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundleSegment<'a> {
+    /// Path from the boundary comment, as written.
+    pub path: &'a str,
+    /// 1-based line range of the code in the bundle, comment line excluded.
+    pub start_line: usize,
+    pub end_line: usize,
+    /// The code, without the comment line and trailing blank lines.
+    pub text: &'a str,
+    pub wrapper: Wrapper,
+}
+
+/// One segment written under `bundled/`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BundledEntry {
+    /// Path relative to the output directory (`bundled/...`).
+    pub file: String,
+    /// Path from the boundary comment.
+    pub path: String,
+    /// Index of the bundled module it was split from.
+    pub module: usize,
+    /// 1-based, inclusive line range in that module's contents.
+    pub start_line: usize,
+    pub end_line: usize,
+    pub wrapper: Wrapper,
+    pub size: usize,
+    pub sha256: String,
+}
+
+/// Splits bundled `contents` at the boundary comments. Code before the first
+/// boundary (Bun's runtime helpers) belongs to no segment.
+pub fn split_bundle(contents: &str) -> Vec<BundleSegment<'_>> {
+    // Byte offset of every line start.
+    let mut starts = vec![0];
+    starts.extend(contents.match_indices('\n').map(|(at, _)| at + 1));
+    let line = |i: usize| {
+        let start = starts[i];
+        let end = starts.get(i + 1).map_or(contents.len(), |s| s - 1);
+        contents[start..end].trim_end_matches('\r')
+    };
+    let line_count = if contents.ends_with('\n') { starts.len() - 1 } else { starts.len() };
+
+    let boundaries: Vec<(usize, &str)> = (0..line_count)
+        .filter_map(|i| {
+            let after_break = i == 0 || line(i - 1).trim().is_empty() || line(i - 1).starts_with("// @bun");
+            boundary_path(line(i)).filter(|_| after_break).map(|p| (i, p))
+        })
+        .collect();
+
+    let mut segments = Vec::with_capacity(boundaries.len());
+    for (k, &(comment, path)) in boundaries.iter().enumerate() {
+        let next = boundaries.get(k + 1).map_or(line_count, |b| b.0);
+        let first = comment + 1;
+        let mut last = next;
+        while last > first && line(last - 1).trim().is_empty() {
+            last -= 1;
+        }
+        if last == first {
+            continue;
+        }
+        let start = starts[first];
+        let end = starts[last - 1] + line(last - 1).len();
+        let text = &contents[start..end];
+        segments.push(BundleSegment {
+            path,
+            start_line: first + 1,
+            end_line: last,
+            text,
+            wrapper: wrapper_of(text),
+        });
+    }
+    segments
+}
+
+/// The path of a `// node_modules/pkg/file.js` line: no spaces, a known extension.
+fn boundary_path(line: &str) -> Option<&str> {
+    let path = line.strip_prefix("// ")?;
+    if path.is_empty() || path.contains(char::is_whitespace) {
+        return None;
+    }
+    let ext = path.rsplit_once('.')?.1;
+    SOURCE_EXTENSIONS.contains(&ext).then_some(path)
+}
+
+/// The wrapper declared by a top-level (column 0) `var x = __commonJS(` or
+/// `var x = __esm(` statement. Bun puts an ESM file's export object and hoisted
+/// functions before its `__esm` call, so any top-level line counts; indented
+/// code and string contents never do.
+fn wrapper_of(text: &str) -> Wrapper {
+    for line in text.lines().filter(|l| l.starts_with("var ")) {
+        if line.contains("= __commonJS(") {
+            return Wrapper::Cjs;
+        }
+        if line.contains("= __esm(") {
+            return Wrapper::Esm;
+        }
+    }
+    Wrapper::None
+}
+
+/// Splits every selected JavaScript module and writes its segments to
+/// `out_dir/bundled/`. The same file (path and content) found in several
+/// modules is written once; each occurrence still gets an entry.
+pub fn write_bundled(
+    graph: &ModuleGraph<'_>,
+    out_dir: &Path,
+    select: impl Fn(&Module<'_>) -> bool,
+) -> Result<Vec<BundledEntry>> {
+    let mut entries: Vec<BundledEntry> = Vec::new();
+    // Normalized path -> (sha256, file) written under it, one per distinct content.
+    let mut by_path: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+    for module in graph.modules.iter().filter(|m| m.loader.is_javascript() && select(m)) {
+        let Ok(contents) = std::str::from_utf8(module.contents) else {
+            continue;
+        };
+        for segment in split_bundle(contents) {
+            let rel = normalize_source_path(segment.path);
+            if rel.is_empty() {
+                continue;
+            }
+            let sha256 = sha256_hex(segment.text.as_bytes());
+            let variants = by_path.entry(rel.clone()).or_default();
+            let file = match variants.iter().find(|(sha, _)| *sha == sha256) {
+                Some((_, file)) => file.clone(),
+                None => {
+                    let (file, full) = free_path(out_dir, BUNDLED_DIR, &rel, variants.len());
+                    if let Some(parent) = full.parent() {
+                        fs::create_dir_all(parent).with_context(|| format!("create dir {:?}", parent))?;
+                    }
+                    fs::write(&full, segment.text).with_context(|| format!("write bundled {:?}", full))?;
+                    variants.push((sha256.clone(), file.clone()));
+                    file
+                }
+            };
+            entries.push(BundledEntry {
+                file,
+                path: segment.path.to_string(),
+                module: module.index,
+                start_line: segment.start_line,
+                end_line: segment.end_line,
+                wrapper: segment.wrapper,
+                size: segment.text.len(),
+                sha256,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = "// @bun
+// node_modules/dep/index.js
+var require_dep = __commonJS((exports, module) => {
+  // helper.js
+  module.exports = \"// not/a/boundary.js\";
+});
+
+// src/lazy.ts
+var exports_lazy = {};
+__export(exports_lazy, {
+  x: () => x
+});
+var x;
+var init_lazy = __esm(() => {
+  x = 1;
+});
+
+// src/cli.ts
+// prettier-ignore
+var dep = require_dep();
+init_lazy();
+console.log(dep);
+";
+
+    #[test]
+    fn splits_at_boundary_comments() {
+        let segments = split_bundle(BUNDLE);
+        let summary: Vec<_> = segments.iter().map(|s| (s.path, s.start_line, s.end_line, s.wrapper)).collect();
+        assert_eq!(
+            summary,
+            [
+                // Right after the `// @bun` line.
+                ("node_modules/dep/index.js", 3, 6, Wrapper::Cjs),
+                // After a blank line; the wrapper is found past the first line.
+                ("src/lazy.ts", 9, 16, Wrapper::Esm),
+                ("src/cli.ts", 19, 22, Wrapper::None),
+            ]
+        );
+        // Indented comments and comments inside strings stay in their segment.
+        assert!(segments[0].text.contains("  // helper.js\n"));
+        assert!(segments[0].text.ends_with("});"));
+        let lines: Vec<&str> = BUNDLE.lines().collect();
+        for s in &segments {
+            assert_eq!(s.text, lines[s.start_line - 1..s.end_line].join("\n"));
+        }
+    }
+
+    #[test]
+    fn wrapper_is_a_top_level_statement() {
+        assert_eq!(wrapper_of("// comment\nvar require_a = __commonJS(() => {});"), Wrapper::Cjs);
+        assert_eq!(wrapper_of("var init_a = __esm(() => {});"), Wrapper::Esm);
+        assert_eq!(wrapper_of("var exports_a = {};\nfunction f() {}\nvar init_a = __esm(() => {});"), Wrapper::Esm);
+        // Nested code and string contents are not wrappers.
+        assert_eq!(wrapper_of("function f() {\n  var init_b = __esm(() => {});\n}"), Wrapper::None);
+        assert_eq!(wrapper_of("const s = \"var a = __commonJS(\";"), Wrapper::None);
+        assert_eq!(wrapper_of("console.log(1);"), Wrapper::None);
+    }
+
+    #[test]
+    fn ignores_comments_that_are_not_paths() {
+        let text = "var a = 1;\n// src/no-break.js\nvar b = 2;\n\n// see docs.js for more\nvar c;\n\n// TODO\nvar d;\n";
+        assert!(split_bundle(text).is_empty());
+        assert_eq!(boundary_path("// src/a.ts"), Some("src/a.ts"));
+        assert_eq!(boundary_path("// src/a.png"), None);
+        assert_eq!(boundary_path("//src/a.ts"), None);
+        // A boundary with no code before the next one is dropped.
+        let text = "// a.js\n\n// b.js\nvar b;\r\n";
+        let segments = split_bundle(text);
+        assert_eq!(segments.len(), 1);
+        assert_eq!((segments[0].path, segments[0].start_line, segments[0].end_line), ("b.js", 4, 4));
+        assert_eq!(segments[0].text, "var b;");
+    }
+}
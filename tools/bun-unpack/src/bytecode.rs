@@ -0,0 +1,408 @@
+//! Inspect JavaScriptCore cached bytecode (`--bytecode` builds) and check it
+//! against the source module it belongs to.
+//!
+//! **Interaction**: Used by the CLI `bytecode` subcommand. A module's
+//! `bytecode` part is the JSC cache entry Bun hands to `JSC::CachedBytecode`,
+//! linked to the module's `contents` (written as `<path>.jsc` next to `<path>`).
+//!
+//! JSC writes the entry as one buffer of self-relative offsets with no segment
+//! table; its root object, `JSC::CacheEntry`, sits at offset 0. The fixed part
+//! read here, as laid out on the 64-bit targets Bun ships:
+//!
+//! - 0, `u32`: `m_cacheVersion`, a hash of the JSC/WebKit build. It doubles as
+//!   the magic: a runtime rejects any cache whose value is not its own.
+//! - 16, `u32`: `m_tag`, the kind of code block (program, module, eval).
+//! - 32 / 36, `i32`: `m_key.m_sourceCode` start and end offset in the source
+//!   (UTF-16 code units).
+//! - 56 / 60, `u32`: `m_key.m_flags` and `m_key.m_hash`; the key hash is the
+//!   source's `WTF::StringHasher` hash XOR the flags.
+//! - 72, `i64`: `m_codeBlock`, self-relative offset of the root code block.
+//!
+//! The segments reported are the fixed header, the out-of-line data the header
+//! points at (boot session UUID, source provider, name) and the code block
+//! tree. A header whose tag or offsets are out of range is reported as not
+//! matching this layout, and its source is then not compared.
+
+use serde::Serialize;
+
+use crate::graph::{read_u32_le, read_u64_le, Module, ModuleGraph};
+use crate::manifest::sha256_hex;
+use crate::unpack::bytecode_rel_path;
+
+/// Size of the fixed part of `JSC::CacheEntry`.
+pub const CACHE_HEADER_SIZE: usize = 80;
+
+/// `WTF::StringHasher` keeps the top 8 bits of a string hash for flags.
+const STRING_HASH_MASK: u32 = 0x00ff_ffff;
+
+/// Report for one module with bytecode, or one whose source expects bytecode.
+#[derive(Clone, Debug, Serialize)]
+pub struct BytecodeReport {
+    pub index: usize,
+    /// Path of the `.jsc` file `unpack` writes.
+    pub file: String,
+    /// Offset of the bytecode inside the blob.
+    pub offset: u32,
+    pub size: usize,
+    pub sha256: String,
+    /// `m_cacheVersion`: the JSC build the bytecode was produced for.
+    pub cache_version: Option<u32>,
+    /// The rest of the fixed header; `None` if it does not match the layout.
+    pub header: Option<CacheHeader>,
+    /// Source module the bytecode belongs to.
+    pub source: SourceLink,
+    /// Mismatches between the bytecode, its source and the rest of the graph.
+    pub issues: Vec<String>,
+}
+
+/// Fixed `JSC::CacheEntry` fields after the cache version.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CacheHeader {
+    /// `program`, `module` or `eval`.
+    pub kind: &'static str,
+    /// Source range the bytecode was compiled from, in UTF-16 code units.
+    pub source_start: u32,
+    pub source_end: u32,
+    pub key_flags: u32,
+    /// Source hash the cache is tied to (key hash XOR flags, top 8 bits masked).
+    pub source_hash: u32,
+    /// Header, out-of-line data and code block, in buffer order.
+    pub segments: Vec<Segment>,
+}
+
+/// A byte range of the cache entry.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Segment {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// The module whose `contents` the bytecode was compiled from.
+#[derive(Clone, Debug, Serialize)]
+pub struct SourceLink {
+    pub rel_path: String,
+    pub size: usize,
+    /// Length JSC sees: UTF-16 code units of `contents`.
+    pub length: usize,
+    /// `WTF::StringHasher` hash of `contents`, top 8 bits masked.
+    pub hash: u32,
+    pub sha256: String,
+    /// `contents` starts with Bun's `// @bun @bytecode` pragma.
+    pub bytecode_pragma: bool,
+}
+
+/// Inspects every module that has bytecode or whose source carries the
+/// `@bytecode` pragma, in table order. Cache versions that differ from the
+/// entry point's (or the first seen) are flagged, since one runtime accepts
+/// only one.
+pub fn inspect_bytecode(graph: &ModuleGraph<'_>) -> Vec<BytecodeReport> {
+    let expected_version = graph
+        .entry_point()
+        .filter(|m| m.bytecode.len() >= 4)
+        .or_else(|| graph.modules.iter().find(|m| m.bytecode.len() >= 4))
+        .map(|m| read_u32_le(m.bytecode, 0));
+    graph
+        .modules
+        .iter()
+        .filter(|m| !m.bytecode.is_empty() || has_bytecode_pragma(m.contents))
+        .map(|m| inspect_module(m, expected_version))
+        .collect()
+}
+
+fn inspect_module(module: &Module<'_>, expected_version: Option<u32>) -> BytecodeReport {
+    let bytecode = module.bytecode;
+    let rel_path = module.rel_path();
+    let units = utf16_units(module.contents);
+    let source = SourceLink {
+        rel_path: rel_path.clone(),
+        size: module.contents.len(),
+        length: units.len(),
+        hash: string_hash(&units),
+        sha256: sha256_hex(module.contents),
+        bytecode_pragma: has_bytecode_pragma(module.contents),
+    };
+    let cache_version = (bytecode.len() >= 4).then(|| read_u32_le(bytecode, 0));
+    let header = parse_header(bytecode);
+
+    let mut issues = Vec::new();
+    if bytecode.is_empty() {
+        issues.push("source has the @bytecode pragma but the module has no bytecode".to_string());
+    } else {
+        if let (Some(v), Some(expected)) = (cache_version, expected_version) {
+            if v != expected {
+                issues.push(format!("cache version {:#010x} differs from {:#010x}", v, expected));
+            }
+        }
+        if header.is_none() {
+            issues.push(format!(
+                "{} byte header does not match the JSC CacheEntry layout; source not compared",
+                bytecode.len().min(CACHE_HEADER_SIZE)
+            ));
+        }
+        if module.contents.is_empty() {
+            issues.push("bytecode has no source module contents".to_string());
+        } else {
+            if !source.bytecode_pragma {
+                issues.push("source has no @bytecode pragma".to_string());
+            }
+            if let Some(h) = &header {
+                let length = (h.source_end - h.source_start) as usize;
+                if length != source.length {
+                    issues.push(format!(
+                        "bytecode was compiled from {} UTF-16 units of source, module has {}",
+                        length, source.length
+                    ));
+                }
+                if h.source_hash != source.hash {
+                    issues.push(format!(
+                        "source hash {:#08x} differs from the module's {:#08x}",
+                        h.source_hash, source.hash
+                    ));
+                }
+            }
+        }
+    }
+
+    BytecodeReport {
+        index: module.index,
+        file: bytecode_rel_path(&rel_path),
+        offset: module.pointers.bytecode.offset,
+        size: bytecode.len(),
+        sha256: sha256_hex(bytecode),
+        cache_version,
+        header,
+        source,
+        issues,
+    }
+}
+
+/// Reads the fixed `CacheEntry` fields; `None` if the buffer is too short, the
+/// tag is unknown or an offset points outside the buffer.
+fn parse_header(bytecode: &[u8]) -> Option<CacheHeader> {
+    if bytecode.len() < CACHE_HEADER_SIZE {
+        return None;
+    }
+    let kind = match read_u32_le(bytecode, 16) {
+        0 => "program",
+        1 => "module",
+        2 => "eval",
+        _ => return None,
+    };
+    let (source_start, source_end) = (read_u32_le(bytecode, 32), read_u32_le(bytecode, 36));
+    if source_start > source_end || source_end > i32::MAX as u32 {
+        return None;
+    }
+    let code_block = (read_u64_le(bytecode, 72) as i64).checked_add(72)?;
+    let code_block = usize::try_from(code_block)
+        .ok()
+        .filter(|&at| (CACHE_HEADER_SIZE..bytecode.len()).contains(&at))?;
+    let (key_flags, key_hash) = (read_u32_le(bytecode, 56), read_u32_le(bytecode, 60));
+    Some(CacheHeader {
+        kind,
+        source_start,
+        source_end,
+        key_flags,
+        source_hash: (key_hash ^ key_flags) & STRING_HASH_MASK,
+        segments: vec![
+            Segment { name: "header", offset: 0, size: CACHE_HEADER_SIZE },
+            Segment { name: "data", offset: CACHE_HEADER_SIZE, size: code_block - CACHE_HEADER_SIZE },
+            Segment { name: "code block", offset: code_block, size: bytecode.len() - code_block },
+        ],
+    })
+}
+
+/// Bun marks transpiled output that has bytecode with `// @bun @bytecode` on the first line.
+fn has_bytecode_pragma(contents: &[u8]) -> bool {
+    let first_line = contents.split(|&b| b == b'\n').next().unwrap_or_default();
+    first_line.starts_with(b"// @bun") && first_line.windows(9).any(|w| w == b"@bytecode")
+}
+
+/// Characters of `contents` as JSC sees them: UTF-16 code units of UTF-8
+/// text, or one Latin-1 character per byte otherwise.
+fn utf16_units(contents: &[u8]) -> Vec<u16> {
+    match std::str::from_utf8(contents) {
+        Ok(s) => s.encode_utf16().collect(),
+        Err(_) => contents.iter().map(|&b| u16::from(b)).collect(),
+    }
+}
+
+/// `WTF::StringHasher::computeHashAndMaskTop8Bits`: Paul Hsieh's SuperFastHash
+/// over pairs of characters, top 8 bits masked, never 0.
+fn string_hash(units: &[u16]) -> u32 {
+    let mut hash: u32 = 0x9e37_79b9;
+    let mut pairs = units.chunks_exact(2);
+    for pair in &mut pairs {
+        hash = hash.wrapping_add(u32::from(pair[0]));
+        hash = (hash << 16) ^ ((u32::from(pair[1]) << 11) ^ hash);
+        hash = hash.wrapping_add(hash >> 11);
+    }
+    if let [last] = pairs.remainder() {
+        hash = hash.wrapping_add(u32::from(*last));
+        hash ^= hash << 11;
+        hash = hash.wrapping_add(hash >> 17);
+    }
+    hash ^= hash << 3;
+    hash = hash.wrapping_add(hash >> 5);
+    hash ^= hash << 2;
+    hash = hash.wrapping_add(hash >> 15);
+    hash ^= hash << 10;
+    hash &= STRING_HASH_MASK;
+    if hash == 0 {
+        0x0080_0000
+    } else {
+        hash
+    }
+}
+
+/// Formats `reports` as one block per module plus a summary line.
+pub fn format_bytecode_report(reports: &[BytecodeReport]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for r in reports {
+        let version = r.cache_version.map_or("-".to_string(), |v| format!("{:#010x}", v));
+        if r.size == 0 {
+            lines.push(format!("[{}] {} (missing)", r.index, r.file));
+        } else {
+            lines.push(format!("[{}] {} ({} bytes at {:#x})", r.index, r.file, r.size, r.offset));
+        }
+        lines.push(format!("    cache version: {}", version));
+        if let Some(h) = &r.header {
+            lines.push(format!(
+                "    header: {} code block, source {}..{}, source hash {:#08x}, key flags {:#x}",
+                h.kind, h.source_start, h.source_end, h.source_hash, h.key_flags
+            ));
+            let segments: Vec<String> =
+                h.segments.iter().map(|s| format!("{} {} at {:#x}", s.name, s.size, s.offset)).collect();
+            lines.push(format!("    segments: {}", segments.join(", ")));
+        }
+        lines.push(format!(
+            "    source: {} ({} bytes, {} UTF-16 units, hash {:#08x}, sha256 {}){}",
+            r.source.rel_path,
+            r.source.size,
+            r.source.length,
+            r.source.hash,
+            r.source.sha256,
+            if r.source.bytecode_pragma { ", @bytecode" } else { "" }
+        ));
+        for issue in &r.issues {
+            lines.push(format!("    ! {}", issue));
+        }
+    }
+    let flagged = reports.iter().filter(|r| !r.issues.is_empty()).count();
+    lines.push(format!("{} bytecode modules, {} with issues", reports.len(), flagged));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::{sample_blob, BYTECODE};
+    use crate::layout::LAYOUTS;
+
+    const CLI: &[u8] = b"// @bun @bytecode\nconsole.log(1);\n";
+
+    /// A cache entry for `source` with `data` bytes between the header and a
+    /// 32-byte code block.
+    fn cache_entry(version: u32, source: &[u8], data: usize) -> Vec<u8> {
+        let units = utf16_units(source);
+        let flags = 0x21u32;
+        let mut entry = vec![0; CACHE_HEADER_SIZE + data + 32];
+        entry[0..4].copy_from_slice(&version.to_le_bytes());
+        entry[16..20].copy_from_slice(&1u32.to_le_bytes());
+        entry[36..40].copy_from_slice(&(units.len() as u32).to_le_bytes());
+        entry[56..60].copy_from_slice(&flags.to_le_bytes());
+        entry[60..64].copy_from_slice(&(string_hash(&units) ^ flags).to_le_bytes());
+        entry[72..80].copy_from_slice(&((CACHE_HEADER_SIZE + data - 72) as u64).to_le_bytes());
+        entry
+    }
+
+    #[test]
+    fn reports_header_and_matches_source() {
+        let entry = cache_entry(0x0403_0201, CLI, 16);
+        let blob = sample_blob(&LAYOUTS[0]);
+        let mut graph = ModuleGraph::parse(&blob).unwrap();
+        graph.modules[0].contents = CLI;
+        graph.modules[0].bytecode = &entry;
+        let reports = inspect_bytecode(&graph);
+        assert_eq!(reports.len(), 1);
+        let r = &reports[0];
+        assert_eq!(r.file, "root/cli.js.jsc");
+        assert_eq!(r.cache_version, Some(0x0403_0201));
+        assert!(r.issues.is_empty(), "{:?}", r.issues);
+        let header = r.header.as_ref().unwrap();
+        assert_eq!(header.kind, "module");
+        assert_eq!((header.source_start, header.source_end), (0, CLI.len() as u32));
+        assert_eq!(header.source_hash, r.source.hash);
+        assert_eq!(
+            header.segments.iter().map(|s| (s.name, s.offset, s.size)).collect::<Vec<_>>(),
+            [("header", 0, 80), ("data", 80, 16), ("code block", 96, 32)]
+        );
+        assert_eq!(format_bytecode_report(&reports).last().unwrap(), "1 bytecode modules, 0 with issues");
+    }
+
+    #[test]
+    fn flags_source_mismatches() {
+        let entry = cache_entry(1, CLI, 0);
+        let other = cache_entry(2, b"// @bun @bytecode\nconsole.log(2);\n", 0);
+        let blob = sample_blob(&LAYOUTS[0]);
+        let mut graph = ModuleGraph::parse(&blob).unwrap();
+        graph.modules[0].contents = b"// @bun @bytecode\nconsole.log(10);\n";
+        graph.modules[0].bytecode = &entry;
+        graph.modules[1].contents = CLI;
+        graph.modules[1].bytecode = &other;
+        let reports = inspect_bytecode(&graph);
+        assert!(reports[0].issues[0].starts_with("bytecode was compiled from 34 UTF-16 units of source, module has 35"));
+        assert!(reports[0].issues[1].starts_with("source hash"));
+        // Same length, other text: only the hash differs; the version differs from the entry point's.
+        assert_eq!(reports[1].issues.len(), 2);
+        assert_eq!(reports[1].issues[0], "cache version 0x00000002 differs from 0x00000001");
+        assert!(reports[1].issues[1].starts_with("source hash"));
+    }
+
+    #[test]
+    fn rejects_other_layouts() {
+        let mut bad_tag = cache_entry(1, CLI, 0);
+        bad_tag[16] = 7;
+        let mut bad_code_block = cache_entry(1, CLI, 0);
+        bad_code_block[72..80].copy_from_slice(&(-8i64 as u64).to_le_bytes());
+        let mut bad_range = cache_entry(1, CLI, 0);
+        bad_range[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        for entry in [&bad_tag, &bad_code_block, &bad_range, &BYTECODE.to_vec()] {
+            assert_eq!(parse_header(entry), None);
+        }
+
+        let blob = sample_blob(&LAYOUTS[0]);
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let reports = inspect_bytecode(&graph);
+        assert_eq!(reports[0].cache_version, Some(read_u32_le(BYTECODE, 0)));
+        assert_eq!(
+            reports[0].issues,
+            [
+                "40 byte header does not match the JSC CacheEntry layout; source not compared",
+                "source has no @bytecode pragma"
+            ]
+        );
+    }
+
+    #[test]
+    fn pragma_without_bytecode() {
+        let blob = sample_blob(&LAYOUTS[0]);
+        let mut graph = ModuleGraph::parse(&blob).unwrap();
+        graph.modules[1].contents = b"// @bun @bytecode\nexport const x = 1;\n";
+        let reports = inspect_bytecode(&graph);
+        assert_eq!(reports[1].issues, ["source has the @bytecode pragma but the module has no bytecode"]);
+    }
+
+    #[test]
+    fn string_hash_counts_utf16_units() {
+        // Latin-1 bytes and their UTF-8 encoding hash alike; odd lengths use the tail step.
+        assert_eq!(string_hash(&utf16_units(b"caf\xe9")), string_hash(&utf16_units("café".as_bytes())));
+        assert_ne!(string_hash(&utf16_units(b"ab")), string_hash(&utf16_units(b"ba")));
+        assert_ne!(string_hash(&utf16_units(b"abc")), string_hash(&utf16_units(b"ab")));
+        assert_eq!(utf16_units("😀".as_bytes()).len(), 2);
+        for s in [&b""[..], b"a", b"console.log(1);"] {
+            let hash = string_hash(&utf16_units(s));
+            assert!(hash != 0 && hash & !STRING_HASH_MASK == 0);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Module-level comparison of two module graphs (e.g. two vendor releases).
+//!
+//! **Interaction**: Used by the CLI `diff` subcommand. Modules are matched by
+//! their `rel_path`, so the same module matches across platforms whose virtual
+//! roots differ (`B:/~BUN/root/` vs `/$bunfs/root/`); each part is compared by
+//! sha256. Modules whose `rel_path` is rejected, or shared by several modules
+//! of either graph, are matched by raw name instead, and shared paths are
+//! reported in `ambiguous`. Unified text diffs of changed JavaScript are produced on request
+//! with `similar`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+use similar::TextDiff;
+
+use crate::graph::{Module, ModuleGraph};
+use crate::manifest::sha256_hex;
+
+/// Differences between an old and a new graph.
+#[derive(Clone, Debug, Serialize)]
+pub struct GraphDiff {
+    /// Entry point name, if it changed.
+    pub entry_point: Option<Change<String>>,
+    /// Baked exec argv, if it changed.
+    pub exec_argv: Option<Change<Vec<String>>>,
+    /// Modules only in the new graph.
+    pub added: Vec<ModuleSummary>,
+    /// Modules only in the old graph.
+    pub removed: Vec<ModuleSummary>,
+    /// Modules in both graphs whose parts or kinds differ.
+    pub changed: Vec<ModuleChange>,
+    /// Number of modules identical in both graphs.
+    pub unchanged: usize,
+    /// `rel_path`s shared by several modules of either graph; those modules
+    /// were matched by raw name.
+    pub ambiguous: Vec<String>,
+}
+
+/// An old and a new value.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+/// A module present in only one graph.
+#[derive(Clone, Debug, Serialize)]
+pub struct ModuleSummary {
+    pub rel_path: String,
+    pub name: String,
+    pub loader: String,
+    /// Size of `contents`.
+    pub size: usize,
+    /// sha256 of `contents`.
+    pub sha256: String,
+}
+
+/// A module present in both graphs with different parts or kinds.
+#[derive(Clone, Debug, Serialize)]
+pub struct ModuleChange {
+    pub rel_path: String,
+    /// Parts (`contents`, `sourcemap`, `bytecode`) whose bytes differ.
+    pub parts: Vec<PartChange>,
+    /// Scalar fields (`loader`, `module_format`, ...) whose values differ.
+    pub kinds: Vec<KindChange>,
+    /// Unified diff of `contents`; only for JavaScript modules when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_diff: Option<String>,
+}
+
+/// Old and new size and hash of one part.
+#[derive(Clone, Debug, Serialize)]
+pub struct PartChange {
+    pub part: &'static str,
+    pub old_size: usize,
+    pub new_size: usize,
+    /// `new_size - old_size`.
+    pub delta: i64,
+    pub old_sha256: String,
+    pub new_sha256: String,
+}
+
+/// Old and new value of one scalar field.
+#[derive(Clone, Debug, Serialize)]
+pub struct KindChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// Options for [`diff_graphs`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiffOptions {
+    /// Produce a unified diff of changed JavaScript `contents` with this many
+    /// context lines; `None` for no text diffs.
+    pub text_context: Option<usize>,
+}
+
+/// Compares `old` with `new`. Modules whose name is rejected by
+/// `name_to_rel_path`, or whose `rel_path` is shared by several modules of
+/// either graph, are matched by their raw name instead.
+pub fn diff_graphs(old: &ModuleGraph<'_>, new: &ModuleGraph<'_>, options: &DiffOptions) -> GraphDiff {
+    let mut shared = shared_rel_paths(old);
+    shared.extend(shared_rel_paths(new));
+    let old_modules = by_key(old, &shared);
+    let new_modules = by_key(new, &shared);
+    let mut diff = GraphDiff {
+        entry_point: None,
+        exec_argv: None,
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        unchanged: 0,
+        ambiguous: shared.into_iter().collect(),
+    };
+
+    let entry = |g: &ModuleGraph<'_>| g.entry_point().map(|m| m.name_str().into_owned()).unwrap_or_default();
+    let (old_entry, new_entry) = (entry(old), entry(new));
+    if old_entry != new_entry {
+        diff.entry_point = Some(Change { old: old_entry, new: new_entry });
+    }
+    let (old_argv, new_argv) = (old.compile_exec_argv(), new.compile_exec_argv());
+    if old_argv != new_argv {
+        diff.exec_argv = Some(Change { old: old_argv, new: new_argv });
+    }
+
+    for (key, m) in &old_modules {
+        match new_modules.get(key) {
+            None => diff.removed.push(summary(key, m)),
+            Some(n) => match module_change(key, m, n, options) {
+                Some(change) => diff.changed.push(change),
+                None => diff.unchanged += 1,
+            },
+        }
+    }
+    for (key, n) in &new_modules {
+        if !old_modules.contains_key(key) {
+            diff.added.push(summary(key, n));
+        }
+    }
+    diff
+}
+
+/// Non-empty `rel_path`s that more than one module of `graph` maps to.
+fn shared_rel_paths(graph: &ModuleGraph<'_>) -> BTreeSet<String> {
+    let mut seen = BTreeSet::new();
+    graph
+        .modules
+        .iter()
+        .map(Module::rel_path)
+        .filter(|rel| !rel.is_empty() && !seen.insert(rel.clone()))
+        .collect()
+}
+
+/// Modules keyed by `rel_path` (raw name if rejected or in `shared`), in
+/// sorted order. Modules whose keys still collide (equal names) get their
+/// table index appended, so none is dropped.
+fn by_key<'g, 'a>(graph: &'g ModuleGraph<'a>, shared: &BTreeSet<String>) -> BTreeMap<String, &'g Module<'a>> {
+    let mut modules = BTreeMap::new();
+    for m in &graph.modules {
+        let rel = m.rel_path();
+        let mut key = if rel.is_empty() || shared.contains(&rel) { m.name_str().into_owned() } else { rel };
+        if modules.contains_key(&key) {
+            key = format!("{}#{}", key, m.index);
+        }
+        modules.insert(key, m);
+    }
+    modules
+}
+
+fn summary(key: &str, m: &Module<'_>) -> ModuleSummary {
+    ModuleSummary {
+        rel_path: key.to_string(),
+        name: m.name_str().into_owned(),
+        loader: m.loader.to_string(),
+        size: m.contents.len(),
+        sha256: sha256_hex(m.contents),
+    }
+}
+
+fn module_change(key: &str, old: &Module<'_>, new: &Module<'_>, options: &DiffOptions) -> Option<ModuleChange> {
+    let mut parts = Vec::new();
+    for (part, a, b) in [
+        ("contents", old.contents, new.contents),
+        ("sourcemap", old.sourcemap, new.sourcemap),
+        ("bytecode", old.bytecode, new.bytecode),
+    ] {
+        if a != b {
+            parts.push(PartChange {
+                part,
+                old_size: a.len(),
+                new_size: b.len(),
+                delta: b.len() as i64 - a.len() as i64,
+                old_sha256: sha256_hex(a),
+                new_sha256: sha256_hex(b),
+            });
+        }
+    }
+    let mut kinds = Vec::new();
+    for (field, a, b) in [
+        ("encoding", old.encoding.to_string(), new.encoding.to_string()),
+        ("loader", old.loader.to_string(), new.loader.to_string()),
+        ("module_format", old.module_format.to_string(), new.module_format.to_string()),
+        ("side", old.side.to_string(), new.side.to_string()),
+    ] {
+        if a != b {
+            kinds.push(KindChange { field, old: a, new: b });
+        }
+    }
+    if parts.is_empty() && kinds.is_empty() {
+        return None;
+    }
+    let text_diff = options
+        .text_context
+        .filter(|_| old.contents != new.contents && old.loader.is_javascript() && new.loader.is_javascript())
+        .map(|context| {
+            let (a, b) = (String::from_utf8_lossy(old.contents), String::from_utf8_lossy(new.contents));
+            TextDiff::from_lines(a.as_ref(), b.as_ref())
+                .unified_diff()
+                .context_radius(context)
+                .header(&format!("a/{}", key), &format!("b/{}", key))
+                .to_string()
+        });
+    Some(ModuleChange {
+        rel_path: key.to_string(),
+        parts,
+        kinds,
+        text_diff,
+    })
+}
+
+/// Formats `diff` as one line per change, a summary line, then any text diffs.
+pub fn format_diff(diff: &GraphDiff) -> Vec<String> {
+    let mut lines = Vec::new();
+    for rel in &diff.ambiguous {
+        lines.push(format!("? {}  shared by several modules, matched by raw name", rel));
+    }
+    if let Some(c) = &diff.entry_point {
+        lines.push(format!("entry point: {} -> {}", c.old, c.new));
+    }
+    if let Some(c) = &diff.exec_argv {
+        lines.push(format!("exec argv: {:?} -> {:?}", c.old.join(" "), c.new.join(" ")));
+    }
+    for m in &diff.removed {
+        lines.push(format!("- {}  ({} bytes, {})", m.rel_path, m.size, m.loader));
+    }
+    for m in &diff.added {
+        lines.push(format!("+ {}  ({} bytes, {})", m.rel_path, m.size, m.loader));
+    }
+    for m in &diff.changed {
+        let mut details: Vec<String> = m
+            .parts
+            .iter()
+            .map(|p| format!("{} {} -> {} ({:+})", p.part, p.old_size, p.new_size, p.delta))
+            .collect();
+        details.extend(m.kinds.iter().map(|k| format!("{} {} -> {}", k.field, k.old, k.new)));
+        lines.push(format!("~ {}  {}", m.rel_path, details.join(", ")));
+    }
+    lines.push(format!(
+        "{} added, {} removed, {} changed, {} unchanged",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len(),
+        diff.unchanged
+    ));
+    for text in diff.changed.iter().filter_map(|m| m.text_diff.as_deref()) {
+        lines.extend(text.lines().map(str::to_string));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::layout::LAYOUTS;
+
+    #[test]
+    fn identical_graphs() {
+        let blob = sample_blob(&LAYOUTS[0]);
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let diff = diff_graphs(&graph, &graph, &DiffOptions::default());
+        assert_eq!(diff.unchanged, 2);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty());
+        assert!(diff.ambiguous.is_empty());
+    }
+
+    #[test]
+    fn matches_across_virtual_roots() {
+        let blob = sample_blob(&LAYOUTS[0]);
+        let old = ModuleGraph::parse(&blob).unwrap();
+        let mut new = old.clone();
+        new.modules[1].name = b"/$bunfs/root/lib/util.js";
+        new.modules[1].contents = b"export const x = 2;\n";
+        let diff = diff_graphs(&old, &new, &DiffOptions { text_context: Some(0) });
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].rel_path, "root/lib/util.js");
+        assert_eq!(diff.changed[0].parts[0].part, "contents");
+        assert!(diff.changed[0].text_diff.as_deref().unwrap().contains("+export const x = 2;"));
+    }
+
+    #[test]
+    fn shared_and_rejected_paths_are_kept() {
+        let blob = sample_blob(&LAYOUTS[0]);
+        let old = ModuleGraph::parse(&blob).unwrap();
+        let mut new = old.clone();
+        // Same rel_path under two virtual roots.
+        new.modules[1].name = b"/$bunfs/root/cli.js";
+        let shared = shared_rel_paths(&new);
+        assert_eq!(shared.iter().collect::<Vec<_>>(), ["root/cli.js"]);
+        assert_eq!(by_key(&new, &shared).len(), 2);
+        let diff = diff_graphs(&old, &new, &DiffOptions::default());
+        assert_eq!(diff.ambiguous, ["root/cli.js"]);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.added[0].name, "/$bunfs/root/cli.js");
+        assert_eq!(diff.removed[0].name, "B:/~BUN/root/lib/util.js");
+        assert!(format_diff(&diff)[0].starts_with("? root/cli.js"));
+
+        // Two rejected names that are also equal.
+        new.modules[0].name = b"../evil.js";
+        new.modules[1].name = b"../evil.js";
+        let shared = shared_rel_paths(&new);
+        assert!(shared.is_empty());
+        assert_eq!(by_key(&new, &shared).keys().collect::<Vec<_>>(), ["../evil.js", "../evil.js#1"]);
+    }
+}
@@ -0,0 +1,77 @@
+//! Typed errors for StandaloneModuleGraph decoding.
+//!
+//! **Interaction**: Returned by `ModuleGraph::parse` / `parse_with_layout`. Every
+//! variant records the offending offset or length. When no known layout fits,
+//! `NoMatchingLayout` carries the error each candidate failed with, which
+//! usually tells corrupt input apart from a Bun version this tool predates.
+//! `SourceMapError` covers decoding a module's serialized sourcemap.
+
+use thiserror::Error;
+
+/// Failure while decoding a .bun blob.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseError {
+    #[error("blob too small for trailer + Offsets (len={len}, need at least {need})")]
+    BlobTooSmall { len: usize, need: usize },
+
+    #[error("invalid trailer at offset {offset} (expected \"\\n---- Bun! ----\\n\" at end)")]
+    BadTrailer { offset: usize },
+
+    #[error("Offsets.byte_count {byte_count} out of range (0 < byte_count <= {limit})")]
+    ByteCountOutOfRange { byte_count: u64, limit: usize },
+
+    #[error("modules_ptr out of range (offset={offset}, length={length}, blob_len={blob_len})")]
+    ModulesPtrOutOfRange { offset: u32, length: u32, blob_len: usize },
+
+    #[error("modules_ptr length {length} not divisible by module struct size {struct_size} (unsupported layout?)")]
+    MisalignedModuleTable { length: u32, struct_size: usize },
+
+    #[error("module [{module}] {field} pointer out of range (offset={offset}, length={length}, limit={limit})")]
+    PointerOutOfRange {
+        module: usize,
+        field: &'static str,
+        offset: u32,
+        length: u32,
+        limit: u64,
+    },
+
+    #[error("module [{module}] name is empty or contains NUL")]
+    BadModuleName { module: usize },
+
+    #[error("compile_exec_argv pointer out of range (offset={offset}, length={length}, limit={limit})")]
+    ExecArgvOutOfRange { offset: u32, length: u32, limit: u64 },
+
+    #[error("entry_point_id {entry_point_id} out of range ({modules} modules)")]
+    EntryPointOutOfRange { entry_point_id: u32, modules: usize },
+
+    #[error("no known Bun layout matches (unsupported Bun version or corrupt blob); tried: {}", format_attempts(.tried))]
+    NoMatchingLayout { tried: Vec<LayoutAttempt> },
+}
+
+/// One layout `ModuleGraph::parse` tried and why it was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutAttempt {
+    pub layout: &'static str,
+    pub error: ParseError,
+}
+
+fn format_attempts(tried: &[LayoutAttempt]) -> String {
+    tried
+        .iter()
+        .map(|a| format!("{}: {}", a.layout, a.error))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Failure while decoding a sourcemap in Bun's serialized layout.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SourceMapError {
+    #[error("invalid VLQ mappings at byte {at}")]
+    BadVlq { at: usize },
+
+    #[error("mapping references source {index} but the map has {count} sources")]
+    SourceIndexOutOfRange { index: u32, count: usize },
+
+    #[error("source {source_index}: zstd decompression failed: {reason}")]
+    Zstd { source_index: usize, reason: String },
+}
@@ -0,0 +1,259 @@
+//! Locate and copy the .bun blob out of a PE / ELF / Mach-O executable.
+//!
+//! **Interaction**: `locate_bun_blob` records where the blob lives (format,
+//! byte range, container details); `extract_bun_blob` borrows it out of a
+//! memory-mapped executable (see `map_file`) for the CLI, and `inject` uses the
+//! same location to write a new blob back. The returned bytes have the same
+//! layout regardless of the source format; nothing is copied.
+
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use goblin::mach::constants::cputype::{get_arch_name_from_types, CPU_SUBTYPE_MASK};
+use goblin::mach::{Mach, MachO, SingleArch};
+use goblin::Object;
+use memmap2::{Advice, Mmap};
+
+use crate::graph::{read_u64_le, BUN_TRAILER};
+use crate::layout::LAYOUTS;
+use crate::manifest::ExecutableFormat;
+
+/// Mach-O segment / section Bun writes the module graph into.
+pub const MACHO_SEGMENT: &str = "__BUN";
+pub const MACHO_SECTION: &str = "__bun";
+
+/// Where the blob sits inside an executable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobLocation {
+    pub format: ExecutableFormat,
+    /// Mach-O architecture name (`arm64`, `x86_64`, ...); `None` for PE / ELF.
+    pub arch: Option<String>,
+    /// Byte range of the blob (data .. trailer) within the file.
+    pub range: Range<usize>,
+    pub container: Container,
+}
+
+/// Format-specific details needed to write a new blob back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Container {
+    /// PE `.bun` section: index into the section table, its raw data range, and
+    /// whether the data starts with a u64 LE length.
+    PeSection {
+        index: usize,
+        raw: Range<usize>,
+        length_prefixed: bool,
+    },
+    /// ELF: blob appended at file end, followed by its u64 LE length.
+    ElfTrailer,
+    /// Mach-O `__BUN,__bun` section: raw file range and length-prefix flag.
+    MachOSection {
+        raw: Range<usize>,
+        length_prefixed: bool,
+    },
+    /// Mach-O located only by searching the slice for the trailer (no section found).
+    MachOTrailerScan,
+}
+
+/// Memory-maps `path` read-only, so large executables are paged in on demand
+/// instead of being read into the heap. The file must not be truncated or
+/// rewritten while the map is alive.
+pub fn map_file(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).with_context(|| format!("open {:?}", path))?;
+    // SAFETY: read-only mapping; see the contract above.
+    let map = unsafe { Mmap::map(&file) }.with_context(|| format!("mmap {:?}", path))?;
+    // Parts are visited roughly front to back; let the kernel read ahead and drop behind.
+    let _ = map.advise(Advice::Sequential);
+    Ok(map)
+}
+
+/// Returns the raw StandaloneModuleGraph blob embedded in `exe` (typically a
+/// `map_file` mapping) as a borrowed slice, together with where it was found.
+/// `arch` selects one slice of a universal Mach-O (see `locate_bun_blob`).
+pub fn extract_bun_blob<'a>(exe: &'a [u8], arch: Option<&str>) -> Result<(BlobLocation, &'a [u8])> {
+    let loc = locate_bun_blob(exe, arch)?;
+    let blob = &exe[loc.range.clone()];
+    Ok((loc, blob))
+}
+
+/// Finds the blob inside an in-memory executable. For a universal Mach-O,
+/// `arch` picks the slice (e.g. `arm64`, `x86_64`); without it the binary must
+/// contain exactly one architecture. `arch` is ignored for PE and ELF.
+pub fn locate_bun_blob(buf: &[u8], arch: Option<&str>) -> Result<BlobLocation> {
+    let mut all = locate_all_bun_blobs(buf)?;
+    let names = || {
+        all.iter()
+            .filter_map(|l| l.arch.as_deref())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match arch {
+        Some(want) if all.iter().any(|l| l.arch.is_some()) => {
+            let available = names();
+            all.into_iter()
+                .find(|l| l.arch.as_deref() == Some(want))
+                .with_context(|| format!("no architecture {:?} in Mach-O (available: {})", want, available))
+        }
+        _ if all.len() == 1 => Ok(all.remove(0)),
+        _ => anyhow::bail!(
+            "universal binary contains {} architectures ({}); pick one with --arch or use --all-arches",
+            all.len(),
+            names()
+        ),
+    }
+}
+
+/// Finds every blob in an executable: one for PE / ELF / single-arch Mach-O,
+/// one per architecture for a universal (fat) Mach-O.
+pub fn locate_all_bun_blobs(buf: &[u8]) -> Result<Vec<BlobLocation>> {
+    match Object::parse(buf)? {
+        Object::PE(pe) => {
+            for (index, section) in pe.sections.iter().enumerate() {
+                let is_bun = section.name[..4] == *b".bun"
+                    && section.name[4..].iter().all(|&b| b == 0);
+                if is_bun {
+                    let start = section.pointer_to_raw_data as usize;
+                    let size = section.size_of_raw_data as usize;
+                    if start + size > buf.len() {
+                        anyhow::bail!(".bun section out of bounds (start={}, size={}, file_len={})", start, size, buf.len());
+                    }
+                    let (inner, length_prefixed) = section_blob_range(&buf[start..start + size])
+                        .context("no Bun trailer inside .bun section")?;
+                    return Ok(vec![BlobLocation {
+                        format: ExecutableFormat::Pe,
+                        arch: None,
+                        range: start + inner.start..start + inner.end,
+                        container: Container::PeSection {
+                            index,
+                            raw: start..start + size,
+                            length_prefixed,
+                        },
+                    }]);
+                }
+            }
+            anyhow::bail!("no .bun section found in PE");
+        }
+        Object::Elf(_elf) => {
+            // Linux: last 8 bytes = module graph length (u64 LE), preceding bytes = blob
+            if buf.len() < 8 {
+                anyhow::bail!("ELF too small for trailing length");
+            }
+            let len_end = buf.len() - 8;
+            let len = read_u64_le(buf, len_end) as usize;
+            if len == 0 || len_end < len {
+                anyhow::bail!("invalid module graph length at file end (len={}, file_len={})", len, buf.len());
+            }
+            Ok(vec![BlobLocation {
+                format: ExecutableFormat::Elf,
+                arch: None,
+                range: len_end - len..len_end,
+                container: Container::ElfTrailer,
+            }])
+        }
+        Object::Mach(Mach::Binary(macho)) => Ok(vec![locate_in_macho(buf, 0..buf.len(), &macho)?]),
+        Object::Mach(Mach::Fat(multi)) => {
+            let mut found = Vec::new();
+            for (i, fat_arch) in multi.iter_arches().enumerate() {
+                let fat_arch = fat_arch?;
+                let name = macho_arch_name(fat_arch.cputype, fat_arch.cpusubtype);
+                let SingleArch::MachO(macho) = multi.get(i)? else {
+                    continue;
+                };
+                let start = fat_arch.offset as usize;
+                let slice = start..start + fat_arch.size as usize;
+                found.push(locate_in_macho(buf, slice, &macho).with_context(|| format!("arch {}", name))?);
+            }
+            if found.is_empty() {
+                anyhow::bail!("universal binary contains no Mach-O slices");
+            }
+            Ok(found)
+        }
+        _ => anyhow::bail!("unsupported executable format (use --blob with raw .bun)"),
+    }
+}
+
+/// Locates the blob in one Mach-O image occupying `slice` of `buf` (the whole
+/// file, or one architecture of a universal binary). Reads Bun's `__BUN,__bun`
+/// section from the load commands; falls back to searching the slice for the
+/// trailer when the section is missing.
+fn locate_in_macho(buf: &[u8], slice: Range<usize>, macho: &MachO<'_>) -> Result<BlobLocation> {
+    let arch = Some(macho_arch_name(macho.header.cputype, macho.header.cpusubtype));
+    if slice.end > buf.len() {
+        anyhow::bail!("Mach-O slice out of bounds (start={}, end={}, file_len={})", slice.start, slice.end, buf.len());
+    }
+    for segment in macho.segments.iter() {
+        if segment.name().ok() != Some(MACHO_SEGMENT) {
+            continue;
+        }
+        for (section, _) in segment.sections()? {
+            if section.name().ok() != Some(MACHO_SECTION) {
+                continue;
+            }
+            // Section offsets are relative to the start of this Mach-O image.
+            let start = slice.start + section.offset as usize;
+            let size = section.size as usize;
+            if start + size > slice.end {
+                anyhow::bail!("__bun section out of bounds (start={}, size={}, slice_end={})", start, size, slice.end);
+            }
+            let (inner, length_prefixed) = section_blob_range(&buf[start..start + size])
+                .context("no Bun trailer inside __BUN,__bun section")?;
+            return Ok(BlobLocation {
+                format: ExecutableFormat::MachO,
+                arch,
+                range: start + inner.start..start + inner.end,
+                container: Container::MachOSection {
+                    raw: start..start + size,
+                    length_prefixed,
+                },
+            });
+        }
+    }
+
+    // Fallback: last trailer in the slice; Offsets.byte_count is the data length
+    // before Offsets, whose size depends on the layout, so try each known one.
+    let data = &buf[slice.clone()];
+    let pos = data
+        .windows(BUN_TRAILER.len())
+        .rposition(|w| w == BUN_TRAILER)
+        .context("no __BUN,__bun section and no Bun trailer found in Mach-O")?;
+    let start = LAYOUTS.iter().find_map(|layout| {
+        let offsets_start = pos.checked_sub(layout.offsets_size)?;
+        let byte_count = read_u64_le(data, offsets_start) as usize;
+        (byte_count != 0 && byte_count <= offsets_start).then(|| offsets_start - byte_count)
+    });
+    let start = start.context("invalid byte_count in Mach-O blob for every known layout")?;
+    Ok(BlobLocation {
+        format: ExecutableFormat::MachO,
+        arch,
+        range: slice.start + start..slice.start + pos + BUN_TRAILER.len(),
+        container: Container::MachOTrailerScan,
+    })
+}
+
+/// Architecture name as used by `lipo` (`arm64`, `x86_64`, ...).
+pub fn macho_arch_name(cputype: u32, cpusubtype: u32) -> String {
+    get_arch_name_from_types(cputype, cpusubtype & !CPU_SUBTYPE_MASK)
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("cputype{}", cputype))
+}
+
+/// Blob range inside a section's raw data. Newer Bun prefixes the data with its
+/// u64 LE length (the rest is alignment padding); older layouts store the blob
+/// directly, possibly followed by padding. Returns the range and whether the
+/// length prefix was used.
+pub(crate) fn section_blob_range(data: &[u8]) -> Option<(Range<usize>, bool)> {
+    if data.len() >= 8 {
+        let len = read_u64_le(data, 0);
+        if len >= BUN_TRAILER.len() as u64 && len <= (data.len() - 8) as u64 {
+            let end = 8 + len as usize;
+            if &data[end - BUN_TRAILER.len()..end] == BUN_TRAILER {
+                return Some((8..end, true));
+            }
+        }
+    }
+    let pos = data
+        .windows(BUN_TRAILER.len())
+        .rposition(|w| w == BUN_TRAILER)?;
+    Some((0..pos + BUN_TRAILER.len(), false))
+}
@@ -0,0 +1,49 @@
+//! Glob-based module selection for `extract`.
+//!
+//! **Interaction**: Built from the CLI's `--include` / `--exclude` patterns and
+//! passed to `unpack::write_selected_modules`. Patterns match the sanitized
+//! relative path (`name_to_rel_path`), e.g. `root/src/**`.
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::graph::Module;
+
+/// Include / exclude globs. A module is selected when it matches any include
+/// (or no includes were given) and no exclude.
+#[derive(Clone, Debug)]
+pub struct ModuleFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl ModuleFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_set(include)?)
+        };
+        Ok(Self {
+            include,
+            exclude: build_set(exclude)?,
+        })
+    }
+
+    /// Whether `module` passes the filter. Modules with a rejected name never do.
+    pub fn matches(&self, module: &Module<'_>) -> bool {
+        let rel_path = module.rel_path();
+        if rel_path.is_empty() {
+            return false;
+        }
+        self.include.as_ref().is_none_or(|set| set.is_match(&rel_path)) && !self.exclude.is_match(&rel_path)
+    }
+}
+
+fn build_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for p in patterns {
+        builder.add(Glob::new(p).with_context(|| format!("invalid glob {:?}", p))?);
+    }
+    builder.build().context("build glob set")
+}
@@ -0,0 +1,646 @@
+//! StandaloneModuleGraph parsing: trailer, Offsets and the module table.
+//!
+//! **Interaction**: `ModuleGraph::parse` is the single entry point used by
+//! `unpack` and the CLI; it detects the layout from the table in `layout.rs`
+//! (`parse_with_layout` forces one). All slices borrow from the input blob;
+//! nothing is copied.
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{LayoutAttempt, ParseError};
+use crate::kind::{Encoding, FileSide, Loader, ModuleFormat};
+use crate::layout::{Layout, LAYOUTS};
+use crate::unpack::name_to_rel_path;
+
+/// Trailer at the very end of the blob (16 bytes).
+pub const BUN_TRAILER: &[u8] = b"\n---- Bun! ----\n";
+
+/// CompiledModuleGraphFile size in the current layout (older ones: see `layout.rs`).
+/// Schema.StringPointer = offset(u32) + length(u32). 36 bytes per module.
+pub const MODULE_STRUCT_SIZE: usize = 4 * 8 + 4; // 4×StringPointer + encoding + loader + module_format + side
+
+/// Encoded size of `Offsets` in the current layout (Zig `extern struct`, 8-byte aligned, no padding).
+pub const OFFSETS_SIZE: usize = 32;
+
+/// Offsets struct at end of blob (Bun StandaloneModuleGraph.zig).
+/// All fields little-endian. StringPointer = offset (u32) + length (u32).
+///
+/// | offset | field |
+/// |--------|-------|
+/// | 0      | `byte_count: u64` (usize on 64-bit) |
+/// | 8      | `modules_ptr.offset: u32` |
+/// | 12     | `modules_ptr.length: u32` |
+/// | 16     | `entry_point_id: u32` |
+/// | 20     | `compile_exec_argv_ptr.offset: u32` |
+/// | 24     | `compile_exec_argv_ptr.length: u32` |
+/// | 28     | `flags: u32` |
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Offsets {
+    pub byte_count: u64,
+    pub modules_ptr_offset: u32,
+    pub modules_ptr_length: u32,
+    pub entry_point_id: u32,
+    pub compile_exec_argv_offset: u32,
+    pub compile_exec_argv_length: u32,
+    pub flags: u32,
+}
+
+impl Offsets {
+    /// Decodes the `OFFSETS_SIZE` bytes of `bytes` starting at `at`. Returns
+    /// `None` if fewer than `OFFSETS_SIZE` bytes are available.
+    pub fn decode(bytes: &[u8], at: usize) -> Option<Self> {
+        let b = bytes.get(at..)?.get(..OFFSETS_SIZE)?;
+        Some(Self {
+            byte_count: read_u64_le(b, 0),
+            modules_ptr_offset: read_u32_le(b, 8),
+            modules_ptr_length: read_u32_le(b, 12),
+            entry_point_id: read_u32_le(b, 16),
+            compile_exec_argv_offset: read_u32_le(b, 20),
+            compile_exec_argv_length: read_u32_le(b, 24),
+            flags: read_u32_le(b, 28),
+        })
+    }
+
+    /// Encodes to the on-disk little-endian layout.
+    pub fn encode(&self) -> [u8; OFFSETS_SIZE] {
+        let mut out = [0u8; OFFSETS_SIZE];
+        out[0..8].copy_from_slice(&self.byte_count.to_le_bytes());
+        out[8..12].copy_from_slice(&self.modules_ptr_offset.to_le_bytes());
+        out[12..16].copy_from_slice(&self.modules_ptr_length.to_le_bytes());
+        out[16..20].copy_from_slice(&self.entry_point_id.to_le_bytes());
+        out[20..24].copy_from_slice(&self.compile_exec_argv_offset.to_le_bytes());
+        out[24..28].copy_from_slice(&self.compile_exec_argv_length.to_le_bytes());
+        out[28..32].copy_from_slice(&self.flags.to_le_bytes());
+        out
+    }
+}
+
+/// Names of the `Offsets.flags` bits (Zig `packed struct(u32)`, bit 0 first),
+/// set by `bun build --compile` options such as `--no-compile-autoload-bunfig`.
+pub const FLAG_NAMES: [&str; 4] = [
+    "disable_default_env_files",
+    "disable_autoload_bunfig",
+    "disable_autoload_tsconfig",
+    "disable_autoload_package_json",
+];
+
+impl Offsets {
+    /// Names of the set `flags` bits in bit order; unknown bits are `bit<N>`.
+    pub fn flag_names(&self) -> Vec<String> {
+        (0..32)
+            .filter(|bit| self.flags & (1 << bit) != 0)
+            .map(|bit| match FLAG_NAMES.get(bit) {
+                Some(name) => name.to_string(),
+                None => format!("bit{}", bit),
+            })
+            .collect()
+    }
+}
+
+/// `bun.StringPointer`: a (offset, length) range inside the blob.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StringPointer {
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl StringPointer {
+    /// Reads offset + length (u32 LE each) at `bytes[at..at + 8]`. Caller guarantees bounds.
+    pub(crate) fn read(bytes: &[u8], at: usize) -> Self {
+        Self {
+            offset: read_u32_le(bytes, at),
+            length: read_u32_le(bytes, at + 4),
+        }
+    }
+
+    /// End offset (exclusive) without overflow.
+    pub fn end(&self) -> u64 {
+        self.offset as u64 + self.length as u64
+    }
+}
+
+/// Raw pointers of one module entry, as stored in the table (before bounds checks).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModulePointers {
+    pub name: StringPointer,
+    pub contents: StringPointer,
+    pub sourcemap: StringPointer,
+    pub bytecode: StringPointer,
+}
+
+/// One CompiledModuleGraphFile entry. Each slice borrows from the blob; a
+/// pointer that falls outside the blob yields an empty slice.
+#[derive(Clone, Copy, Debug)]
+pub struct Module<'a> {
+    /// 0-based index in the module table.
+    pub index: usize,
+    pub name: &'a [u8],
+    pub contents: &'a [u8],
+    pub sourcemap: &'a [u8],
+    pub bytecode: &'a [u8],
+    pub encoding: Encoding,
+    pub loader: Loader,
+    pub module_format: ModuleFormat,
+    pub side: FileSide,
+    /// Pointers the slices above were taken from.
+    pub pointers: ModulePointers,
+}
+
+impl<'a> Module<'a> {
+    /// Virtual module name (e.g. `B:/~BUN/root/cli.js`), lossily decoded.
+    pub fn name_str(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.name)
+    }
+
+    /// Safe relative output path for this module; empty if the name is rejected.
+    pub fn rel_path(&self) -> String {
+        name_to_rel_path(&self.name_str())
+    }
+}
+
+/// Parsed module graph. `modules` is in table order; `entry_point_id` indexes it.
+#[derive(Clone, Debug)]
+pub struct ModuleGraph<'a> {
+    /// The whole blob (data, module table, Offsets, trailer).
+    pub blob: &'a [u8],
+    /// Layout the blob was parsed with.
+    pub layout: &'static Layout,
+    pub offsets: Offsets,
+    pub entry_point_id: u32,
+    pub modules: Vec<Module<'a>>,
+}
+
+impl<'a> ModuleGraph<'a> {
+    /// Parses a raw .bun blob (as returned by `extract_bun_blob` or read via `--blob`),
+    /// detecting its layout: each of [`LAYOUTS`] is tried newest first and the
+    /// first one that parses and passes `check_invariants` wins.
+    pub fn parse(blob: &'a [u8]) -> Result<Self, ParseError> {
+        Self::parse_with_hint(blob, None)
+    }
+
+    /// Like `parse`, but tries `hint` (e.g. the layout of the runtime version
+    /// found next to the blob) before the others.
+    pub fn parse_with_hint(blob: &'a [u8], hint: Option<&'static Layout>) -> Result<Self, ParseError> {
+        check_trailer(blob)?;
+        let mut tried = Vec::with_capacity(LAYOUTS.len());
+        let others = LAYOUTS.iter().filter(|l| Some(*l) != hint);
+        for layout in hint.into_iter().chain(others) {
+            match Self::parse_with_layout(blob, layout).and_then(|g| g.check_invariants().map(|()| g)) {
+                Ok(graph) => return Ok(graph),
+                Err(error) => tried.push(LayoutAttempt { layout: layout.name, error }),
+            }
+        }
+        Err(ParseError::NoMatchingLayout { tried })
+    }
+
+    /// Parses `blob` with a fixed layout. Only the structure (trailer, Offsets,
+    /// module table) is checked; a part pointer outside the blob yields an empty slice.
+    pub fn parse_with_layout(blob: &'a [u8], layout: &'static Layout) -> Result<Self, ParseError> {
+        let need = BUN_TRAILER.len() + layout.offsets_size;
+        if blob.len() < need {
+            return Err(ParseError::BlobTooSmall { len: blob.len(), need });
+        }
+        check_trailer(blob)?;
+        let offsets_start = blob.len() - need;
+        let off = layout.decode_offsets(blob, offsets_start).expect("length checked above");
+
+        if off.byte_count == 0 || off.byte_count > offsets_start as u64 {
+            return Err(ParseError::ByteCountOutOfRange {
+                byte_count: off.byte_count,
+                limit: offsets_start,
+            });
+        }
+        let modules_offset = off.modules_ptr_offset as usize;
+        let modules_len = off.modules_ptr_length as usize;
+        if modules_offset
+            .checked_add(modules_len)
+            .is_none_or(|end| end > offsets_start)
+        {
+            return Err(ParseError::ModulesPtrOutOfRange {
+                offset: off.modules_ptr_offset,
+                length: off.modules_ptr_length,
+                blob_len: blob.len(),
+            });
+        }
+        if !modules_len.is_multiple_of(layout.module_size) {
+            return Err(ParseError::MisalignedModuleTable {
+                length: off.modules_ptr_length,
+                struct_size: layout.module_size,
+            });
+        }
+        let num_modules = modules_len / layout.module_size;
+
+        let mut modules = Vec::with_capacity(num_modules);
+        for i in 0..num_modules {
+            let entry = &blob[modules_offset + i * layout.module_size..][..layout.module_size];
+            let [name, contents, sourcemap, bytecode] = layout.read_pointers(entry);
+            let pointers = ModulePointers {
+                name,
+                contents,
+                sourcemap,
+                bytecode,
+            };
+            let [encoding, loader, module_format, side] = layout.read_kinds(entry);
+            let get = |p: StringPointer| slice_at(blob, p.offset as usize, p.length as usize).unwrap_or_default();
+            modules.push(Module {
+                index: i,
+                name: get(pointers.name),
+                contents: get(pointers.contents),
+                sourcemap: get(pointers.sourcemap),
+                bytecode: get(pointers.bytecode),
+                encoding: Encoding::from_u8(encoding),
+                loader: Loader::from_u8(loader),
+                module_format: ModuleFormat::from_u8(module_format),
+                side: FileSide::from_u8(side),
+                pointers,
+            });
+        }
+
+        Ok(Self {
+            blob,
+            layout,
+            offsets: off,
+            entry_point_id: off.entry_point_id,
+            modules,
+        })
+    }
+
+    /// Checks what `parse_with_layout` tolerates: every part pointer (and the
+    /// exec argv pointer) lies within the data before Offsets, every module has
+    /// a non-empty name without NUL bytes, and the entry point indexes an
+    /// existing module. A wrong layout almost always fails here.
+    pub fn check_invariants(&self) -> Result<(), ParseError> {
+        let limit = self.offsets.byte_count;
+        for m in &self.modules {
+            if m.name.is_empty() || m.name.contains(&0) {
+                return Err(ParseError::BadModuleName { module: m.index });
+            }
+            let p = &m.pointers;
+            let parts = [("name", p.name), ("contents", p.contents), ("sourcemap", p.sourcemap), ("bytecode", p.bytecode)];
+            for (field, ptr) in parts {
+                if ptr.end() > limit {
+                    return Err(ParseError::PointerOutOfRange {
+                        module: m.index,
+                        field,
+                        offset: ptr.offset,
+                        length: ptr.length,
+                        limit,
+                    });
+                }
+            }
+        }
+        let argv = StringPointer {
+            offset: self.offsets.compile_exec_argv_offset,
+            length: self.offsets.compile_exec_argv_length,
+        };
+        if argv.end() > limit {
+            return Err(ParseError::ExecArgvOutOfRange {
+                offset: argv.offset,
+                length: argv.length,
+                limit,
+            });
+        }
+        if self.entry_point_id as usize >= self.modules.len() {
+            return Err(ParseError::EntryPointOutOfRange {
+                entry_point_id: self.entry_point_id,
+                modules: self.modules.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Entry point module, if `entry_point_id` is in range.
+    pub fn entry_point(&self) -> Option<&Module<'a>> {
+        self.modules.get(self.entry_point_id as usize)
+    }
+
+    /// Looks up a module by raw name, then by relative path, then by a unique
+    /// path suffix (`cli.js` matches `root/src/cli.js`).
+    pub fn find(&self, name: &str) -> Option<&Module<'a>> {
+        if let Some(m) = self.modules.iter().find(|m| m.name == name.as_bytes()) {
+            return Some(m);
+        }
+        let wanted = name_to_rel_path(name);
+        if wanted.is_empty() {
+            return None;
+        }
+        if let Some(m) = self.modules.iter().find(|m| m.rel_path() == wanted) {
+            return Some(m);
+        }
+        let suffix = format!("/{}", wanted);
+        let mut hits = self.modules.iter().filter(|m| m.rel_path().ends_with(&suffix));
+        match (hits.next(), hits.next()) {
+            (Some(m), None) => Some(m),
+            _ => None,
+        }
+    }
+
+    /// Exec argv baked in at compile time (`--compile-exec-argv`), split on
+    /// whitespace as Bun does at startup; empty if unset.
+    pub fn compile_exec_argv(&self) -> Vec<String> {
+        String::from_utf8_lossy(self.compile_exec_argv_bytes())
+            .split_whitespace()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Raw `compile_exec_argv` bytes; empty if unset or out of range.
+    pub fn compile_exec_argv_bytes(&self) -> &'a [u8] {
+        slice_at(
+            self.blob,
+            self.offsets.compile_exec_argv_offset as usize,
+            self.offsets.compile_exec_argv_length as usize,
+        )
+        .unwrap_or_default()
+    }
+}
+
+/// Fails unless `blob` ends with [`BUN_TRAILER`]; independent of the layout.
+fn check_trailer(blob: &[u8]) -> Result<(), ParseError> {
+    if blob.len() < BUN_TRAILER.len() {
+        return Err(ParseError::BlobTooSmall {
+            len: blob.len(),
+            need: BUN_TRAILER.len(),
+        });
+    }
+    let trailer_start = blob.len() - BUN_TRAILER.len();
+    if &blob[trailer_start..] != BUN_TRAILER {
+        return Err(ParseError::BadTrailer { offset: trailer_start });
+    }
+    Ok(())
+}
+
+/// Returns `blob[offset..offset + length]`, or `None` if out of bounds.
+pub(crate) fn slice_at(blob: &[u8], offset: usize, length: usize) -> Option<&[u8]> {
+    if length == 0 {
+        return Some(&[]);
+    }
+    blob.get(offset..).and_then(|s| s.get(..length))
+}
+
+/// Little-endian u32 at `bytes[at..at + 4]`. Caller guarantees bounds.
+pub(crate) fn read_u32_le(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+/// Little-endian u64 at `bytes[at..at + 8]`. Caller guarantees bounds.
+pub(crate) fn read_u64_le(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const SOURCEMAP: &[u8] = br#"{"version":3,"sources":["../src/cli.ts"],"names":[],"mappings":"AAAA"}"#;
+    pub(crate) const BYTECODE: &[u8] = &[0xab; 40];
+
+    /// Blob in `layout`, encoded by hand rather than with `GraphBuilder`: two
+    /// modules (the first with a sourcemap, and bytecode when the layout has
+    /// it), NUL-terminated strings, 128-aligned bytecode and, for layouts with
+    /// it, an exec argv and flags.
+    pub(crate) fn sample_blob(layout: &'static Layout) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let add = |buf: &mut Vec<u8>, data: &[u8], nul: bool, align: usize| {
+            buf.resize(buf.len().next_multiple_of(align), 0);
+            let ptr = (buf.len() as u32, data.len() as u32);
+            buf.extend_from_slice(data);
+            if nul {
+                buf.push(0);
+            }
+            ptr
+        };
+        let cli = [
+            add(&mut buf, b"B:/~BUN/root/cli.js", true, 1),
+            add(&mut buf, b"console.log(1);\n", true, 1),
+            add(&mut buf, SOURCEMAP, false, 1),
+            if layout.has_bytecode { add(&mut buf, BYTECODE, false, 128) } else { (0, 0) },
+        ];
+        let lib = [
+            add(&mut buf, b"B:/~BUN/root/lib/util.js", true, 1),
+            add(&mut buf, b"export const x = 1;\n", true, 1),
+            (0, 0),
+            (0, 0),
+        ];
+        let argv = if layout.has_exec_argv { add(&mut buf, b"--smol", true, 1) } else { (0, 0) };
+
+        buf.resize(buf.len().next_multiple_of(4), 0);
+        let table_at = buf.len();
+        for (ptrs, kinds) in [(cli, [2u8, 1, 1, 0]), (lib, [2, 1, 1, 0])] {
+            let start = buf.len();
+            for (offset, length) in &ptrs[..layout.pointer_count()] {
+                buf.extend_from_slice(&offset.to_le_bytes());
+                buf.extend_from_slice(&length.to_le_bytes());
+            }
+            buf.extend_from_slice(&kinds[..2]);
+            if layout.has_module_format {
+                buf.push(kinds[2]);
+            }
+            if layout.has_side {
+                buf.push(kinds[3]);
+            }
+            buf.resize(start + layout.module_size, 0);
+        }
+        let table_len = buf.len() - table_at;
+
+        let offsets_at = buf.len();
+        buf.extend_from_slice(&(offsets_at as u64).to_le_bytes());
+        buf.extend_from_slice(&(table_at as u32).to_le_bytes());
+        buf.extend_from_slice(&(table_len as u32).to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        if layout.has_exec_argv {
+            buf.extend_from_slice(&argv.0.to_le_bytes());
+            buf.extend_from_slice(&argv.1.to_le_bytes());
+            buf.extend_from_slice(&1u32.to_le_bytes());
+        }
+        buf.resize(offsets_at + layout.offsets_size, 0);
+        buf.extend_from_slice(BUN_TRAILER);
+        buf
+    }
+
+    /// `sample_blob(layout)` with its Offsets changed by `edit`.
+    pub(crate) fn with_offsets(layout: &'static Layout, edit: impl FnOnce(&mut Offsets)) -> Vec<u8> {
+        let mut blob = sample_blob(layout);
+        let at = blob.len() - BUN_TRAILER.len() - layout.offsets_size;
+        let mut off = layout.decode_offsets(&blob, at).unwrap();
+        edit(&mut off);
+        blob[at..at + layout.offsets_size].copy_from_slice(&layout.encode_offsets(&off));
+        blob
+    }
+
+    /// `sample_blob(layout)` with StringPointer `ptr` of module `module` replaced.
+    pub(crate) fn with_pointer(layout: &'static Layout, module: usize, ptr: usize, value: StringPointer) -> Vec<u8> {
+        let mut blob = sample_blob(layout);
+        let off = ModuleGraph::parse_with_layout(&blob, layout).unwrap().offsets;
+        let at = off.modules_ptr_offset as usize + module * layout.module_size + ptr * 8;
+        blob[at..at + 4].copy_from_slice(&value.offset.to_le_bytes());
+        blob[at + 4..at + 8].copy_from_slice(&value.length.to_le_bytes());
+        blob
+    }
+
+    fn v3() -> &'static Layout {
+        Layout::by_name("v3").unwrap()
+    }
+
+    #[test]
+    fn parse_detects_each_layout() {
+        for layout in LAYOUTS {
+            let blob = sample_blob(layout);
+            let graph = ModuleGraph::parse(&blob).unwrap();
+            assert_eq!(graph.layout.name, layout.name);
+            assert_eq!(graph.modules.len(), 2);
+            let cli = &graph.modules[0];
+            assert_eq!(cli.name, b"B:/~BUN/root/cli.js");
+            assert_eq!(cli.contents, b"console.log(1);\n");
+            assert_eq!(cli.sourcemap, SOURCEMAP);
+            assert_eq!(cli.bytecode, if layout.has_bytecode { BYTECODE } else { b"" });
+            assert_eq!(cli.encoding, Encoding::Utf8);
+            assert_eq!(cli.loader, Loader::Js);
+            assert_eq!(graph.modules[1].rel_path(), "root/lib/util.js");
+            assert_eq!(graph.entry_point().unwrap().index, 0);
+            let argv: &[&str] = if layout.has_exec_argv { &["--smol"] } else { &[] };
+            assert_eq!(graph.compile_exec_argv(), argv);
+        }
+    }
+
+    #[test]
+    fn parse_with_hint_still_picks_the_matching_layout() {
+        for layout in LAYOUTS {
+            let blob = sample_blob(layout);
+            for hint in LAYOUTS {
+                let graph = ModuleGraph::parse_with_hint(&blob, Some(hint)).unwrap();
+                assert_eq!(graph.layout.name, layout.name, "hint {}", hint.name);
+            }
+        }
+    }
+
+    #[test]
+    fn other_layouts_reject_the_blob() {
+        for layout in LAYOUTS {
+            let blob = sample_blob(layout);
+            for other in LAYOUTS.iter().filter(|l| l.name != layout.name) {
+                let parsed = ModuleGraph::parse_with_layout(&blob, other).and_then(|g| g.check_invariants());
+                assert!(parsed.is_err(), "{} blob accepted as {}", layout.name, other.name);
+            }
+        }
+    }
+
+    #[test]
+    fn blob_too_small() {
+        assert_eq!(
+            ModuleGraph::parse(b"short").unwrap_err(),
+            ParseError::BlobTooSmall { len: 5, need: BUN_TRAILER.len() }
+        );
+        assert_eq!(
+            ModuleGraph::parse_with_layout(BUN_TRAILER, v3()).unwrap_err(),
+            ParseError::BlobTooSmall { len: 16, need: 16 + OFFSETS_SIZE }
+        );
+    }
+
+    #[test]
+    fn bad_trailer() {
+        let mut blob = sample_blob(v3());
+        *blob.last_mut().unwrap() = b'?';
+        let offset = blob.len() - BUN_TRAILER.len();
+        assert_eq!(ModuleGraph::parse(&blob).unwrap_err(), ParseError::BadTrailer { offset });
+    }
+
+    #[test]
+    fn byte_count_out_of_range() {
+        for byte_count in [0, u64::MAX] {
+            let blob = with_offsets(v3(), |o| o.byte_count = byte_count);
+            let limit = blob.len() - BUN_TRAILER.len() - OFFSETS_SIZE;
+            assert_eq!(
+                ModuleGraph::parse_with_layout(&blob, v3()).unwrap_err(),
+                ParseError::ByteCountOutOfRange { byte_count, limit }
+            );
+        }
+    }
+
+    #[test]
+    fn modules_ptr_out_of_range() {
+        let blob = with_offsets(v3(), |o| o.modules_ptr_offset = u32::MAX);
+        assert!(matches!(
+            ModuleGraph::parse_with_layout(&blob, v3()).unwrap_err(),
+            ParseError::ModulesPtrOutOfRange { offset: u32::MAX, .. }
+        ));
+    }
+
+    #[test]
+    fn misaligned_module_table() {
+        let blob = with_offsets(v3(), |o| o.modules_ptr_length -= 1);
+        assert_eq!(
+            ModuleGraph::parse_with_layout(&blob, v3()).unwrap_err(),
+            ParseError::MisalignedModuleTable {
+                length: 2 * MODULE_STRUCT_SIZE as u32 - 1,
+                struct_size: MODULE_STRUCT_SIZE
+            }
+        );
+    }
+
+    #[test]
+    fn pointer_out_of_range() {
+        let blob = with_pointer(v3(), 1, 1, StringPointer { offset: 0, length: u32::MAX });
+        let graph = ModuleGraph::parse_with_layout(&blob, v3()).unwrap();
+        assert_eq!(graph.modules[1].contents, b"", "out-of-range part reads as empty");
+        assert!(matches!(
+            graph.check_invariants().unwrap_err(),
+            ParseError::PointerOutOfRange { module: 1, field: "contents", length: u32::MAX, .. }
+        ));
+    }
+
+    #[test]
+    fn bad_module_name() {
+        let blob = with_pointer(v3(), 0, 0, StringPointer::default());
+        let graph = ModuleGraph::parse_with_layout(&blob, v3()).unwrap();
+        assert_eq!(graph.check_invariants().unwrap_err(), ParseError::BadModuleName { module: 0 });
+    }
+
+    #[test]
+    fn exec_argv_out_of_range() {
+        let blob = with_offsets(v3(), |o| o.compile_exec_argv_length = u32::MAX);
+        let graph = ModuleGraph::parse_with_layout(&blob, v3()).unwrap();
+        assert!(matches!(
+            graph.check_invariants().unwrap_err(),
+            ParseError::ExecArgvOutOfRange { length: u32::MAX, .. }
+        ));
+    }
+
+    #[test]
+    fn entry_point_out_of_range() {
+        let blob = with_offsets(v3(), |o| o.entry_point_id = 2);
+        let graph = ModuleGraph::parse_with_layout(&blob, v3()).unwrap();
+        assert_eq!(
+            graph.check_invariants().unwrap_err(),
+            ParseError::EntryPointOutOfRange { entry_point_id: 2, modules: 2 }
+        );
+    }
+
+    #[test]
+    fn no_matching_layout_lists_every_attempt() {
+        let blob = with_offsets(v3(), |o| o.entry_point_id = 2);
+        let ParseError::NoMatchingLayout { tried } = ModuleGraph::parse(&blob).unwrap_err() else {
+            panic!("expected NoMatchingLayout");
+        };
+        let names: Vec<_> = tried.iter().map(|a| a.layout).collect();
+        assert_eq!(names, ["v3", "v2", "v1"]);
+        assert_eq!(tried[0].error, ParseError::EntryPointOutOfRange { entry_point_id: 2, modules: 2 });
+    }
+
+    #[test]
+    fn offsets_encode_decode() {
+        let off = Offsets {
+            byte_count: 0x0102_0304_0506_0708,
+            modules_ptr_offset: 1,
+            modules_ptr_length: 2,
+            entry_point_id: 3,
+            compile_exec_argv_offset: 4,
+            compile_exec_argv_length: 5,
+            flags: 0b101,
+        };
+        assert_eq!(Offsets::decode(&off.encode(), 0), Some(off));
+        assert_eq!(off.flag_names(), ["disable_default_env_files", "disable_autoload_tsconfig"]);
+    }
+}
@@ -0,0 +1,408 @@
+//! Write a (re)packed blob back into a PE / ELF / Mach-O executable.
+//!
+//! **Interaction**: Uses `extract::locate_bun_blob` to find where the current
+//! blob lives, then rewrites that region per format. Used by the CLI `inject`
+//! subcommand after `pack`. Refuses to write when the new blob does not fit;
+//! the result is re-located and compared before it is returned.
+
+use anyhow::{Context, Result};
+use goblin::mach::load_command::CommandVariant;
+use goblin::mach::{Mach, MachO, SingleArch};
+use goblin::pe::section_table::IMAGE_SCN_CNT_INITIALIZED_DATA;
+use goblin::Object;
+
+use crate::extract::{locate_bun_blob, BlobLocation, Container};
+use crate::graph::{read_u32_le, ModuleGraph};
+
+/// Size of one PE section header.
+pub(crate) const PE_SECTION_HEADER_SIZE: usize = 40;
+
+/// Result of `inject_blob`: the new executable bytes and anything the caller
+/// should tell the user (e.g. signatures that are now invalid).
+#[derive(Clone, Debug)]
+pub struct Injected {
+    pub bytes: Vec<u8>,
+    /// Location of the new blob in `bytes`.
+    pub location: BlobLocation,
+    pub warnings: Vec<String>,
+}
+
+/// Replaces the module graph embedded in `exe` with `blob` (a raw .bun blob,
+/// e.g. from `pack`). `blob` must parse as a module graph. `arch` selects the
+/// slice of a universal Mach-O, as in `locate_bun_blob`.
+pub fn inject_blob(exe: &[u8], blob: &[u8], arch: Option<&str>) -> Result<Injected> {
+    ModuleGraph::parse(blob).context("blob to inject does not parse")?;
+    let loc = locate_bun_blob(exe, arch)?;
+    let mut warnings = Vec::new();
+    let bytes = match &loc.container {
+        Container::PeSection {
+            index,
+            raw,
+            length_prefixed,
+        } => inject_pe(exe, *index, raw.clone(), *length_prefixed, blob, &mut warnings)?,
+        Container::ElfTrailer => {
+            let mut out = Vec::with_capacity(loc.range.start + blob.len() + 8);
+            out.extend_from_slice(&exe[..loc.range.start]);
+            out.extend_from_slice(blob);
+            out.extend_from_slice(&(blob.len() as u64).to_le_bytes());
+            out
+        }
+        Container::MachOSection {
+            raw,
+            length_prefixed,
+        } => {
+            let payload = section_payload(blob, *length_prefixed);
+            if payload.len() > raw.len() {
+                anyhow::bail!(
+                    "new blob does not fit: __BUN,__bun holds {} bytes, new layout needs {} (growing Mach-O segments is not supported)",
+                    raw.len(),
+                    payload.len()
+                );
+            }
+            let mut out = exe.to_vec();
+            write_padded(&mut out[raw.clone()], &payload);
+            if macho_has_code_signature(exe, raw.start) {
+                warnings.push(
+                    "Mach-O code signature is now invalid; re-sign (e.g. `codesign --force -s - <file>`) before running".to_string(),
+                );
+            }
+            out
+        }
+        Container::MachOTrailerScan => anyhow::bail!(
+            "Mach-O blob was found only by trailer search (no __BUN,__bun section); cannot inject"
+        ),
+    };
+
+    let location =
+        locate_bun_blob(&bytes, loc.arch.as_deref()).context("re-locate blob in injected executable")?;
+    if bytes[location.range.clone()] != *blob {
+        anyhow::bail!("injected executable does not contain the new blob at its expected location");
+    }
+    Ok(Injected {
+        bytes,
+        location,
+        warnings,
+    })
+}
+
+/// Rewrites the `.bun` section in place, or grows it when it is the last
+/// section and nothing follows it in the file. Fixes VirtualSize,
+/// SizeOfRawData, SizeOfImage and SizeOfInitializedData.
+fn inject_pe(
+    exe: &[u8],
+    index: usize,
+    raw: std::ops::Range<usize>,
+    length_prefixed: bool,
+    blob: &[u8],
+    warnings: &mut Vec<String>,
+) -> Result<Vec<u8>> {
+    let pe = match Object::parse(exe)? {
+        Object::PE(pe) => pe,
+        _ => anyhow::bail!("expected PE"),
+    };
+    let opt = pe.header.optional_header.context("PE has no optional header")?;
+    let file_alignment = opt.windows_fields.file_alignment.max(1) as usize;
+    let section_alignment = opt.windows_fields.section_alignment.max(1) as usize;
+    let bun = &pe.sections[index];
+    let payload = section_payload(blob, length_prefixed);
+
+    let mut out;
+    let raw_size;
+    if payload.len() <= raw.len() {
+        out = exe.to_vec();
+        write_padded(&mut out[raw.clone()], &payload);
+        raw_size = raw.len();
+    } else {
+        let last_in_file = pe.sections.iter().enumerate().all(|(i, s)| {
+            i == index || (s.pointer_to_raw_data as usize + s.size_of_raw_data as usize) <= raw.start
+        });
+        let last_in_memory = pe
+            .sections
+            .iter()
+            .enumerate()
+            .all(|(i, s)| i == index || s.virtual_address < bun.virtual_address);
+        if !last_in_file || !last_in_memory {
+            anyhow::bail!(
+                "new blob does not fit: .bun section holds {} bytes, new layout needs {}, and it is not the last section so it cannot grow",
+                raw.len(),
+                payload.len()
+            );
+        }
+        if raw.end < exe.len() {
+            anyhow::bail!(
+                "new blob does not fit: .bun section holds {} bytes, new layout needs {}, and {} bytes follow it (signature or overlay); strip them first",
+                raw.len(),
+                payload.len(),
+                exe.len() - raw.end
+            );
+        }
+        raw_size = payload.len().next_multiple_of(file_alignment);
+        out = Vec::with_capacity(raw.start + raw_size);
+        out.extend_from_slice(&exe[..raw.start]);
+        out.extend_from_slice(&payload);
+        out.resize(raw.start + raw_size, 0);
+    }
+
+    let header = pe_section_header_offset(exe, index);
+    let virtual_size = u32::try_from(payload.len()).context(".bun section larger than 4GB")?;
+    write_u32_le(&mut out, header + 8, virtual_size);
+    write_u32_le(&mut out, header + 16, raw_size as u32);
+    pe_resize_initialized_data(&mut out, bun.characteristics, bun.size_of_raw_data, raw_size as u32);
+
+    let image_end = pe
+        .sections
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let size = if i == index {
+                virtual_size
+            } else if s.virtual_size == 0 {
+                s.size_of_raw_data
+            } else {
+                s.virtual_size
+            };
+            (s.virtual_address as usize + size as usize).next_multiple_of(section_alignment)
+        })
+        .max()
+        .unwrap_or(0);
+    write_u32_le(&mut out, pe_optional_header_offset(exe) + 56, image_end as u32);
+
+    if pe_certificate_table_size(exe) > 0 {
+        warnings.push("Authenticode signature is now invalid; re-sign or strip it".to_string());
+    }
+    Ok(out)
+}
+
+/// Section contents for `blob`: prefixed with its u64 LE length if the original was.
+fn section_payload(blob: &[u8], length_prefixed: bool) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(blob.len() + 8);
+    if length_prefixed {
+        payload.extend_from_slice(&(blob.len() as u64).to_le_bytes());
+    }
+    payload.extend_from_slice(blob);
+    payload
+}
+
+/// Copies `payload` to the start of `dst` and zeroes the rest.
+fn write_padded(dst: &mut [u8], payload: &[u8]) {
+    dst[..payload.len()].copy_from_slice(payload);
+    dst[payload.len()..].fill(0);
+}
+
+pub(crate) fn write_u32_le(buf: &mut [u8], at: usize, value: u32) {
+    buf[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// File offset of the optional header (after `PE\0\0` and the 20-byte COFF header).
+pub(crate) fn pe_optional_header_offset(exe: &[u8]) -> usize {
+    read_u32_le(exe, 0x3c) as usize + 4 + 20
+}
+
+/// File offset of section header `index`.
+pub(crate) fn pe_section_header_offset(exe: &[u8], index: usize) -> usize {
+    let coff = read_u32_le(exe, 0x3c) as usize + 4;
+    let size_of_optional_header = u16::from_le_bytes([exe[coff + 16], exe[coff + 17]]) as usize;
+    coff + 20 + size_of_optional_header + index * PE_SECTION_HEADER_SIZE
+}
+
+/// Adjusts SizeOfInitializedData in `out` for a section whose raw size changes
+/// from `old` to `new` bytes; only sections marked as initialized data count.
+pub(crate) fn pe_resize_initialized_data(out: &mut [u8], characteristics: u32, old: u32, new: u32) {
+    if characteristics & IMAGE_SCN_CNT_INITIALIZED_DATA == 0 {
+        return;
+    }
+    let at = pe_optional_header_offset(out) + 8;
+    let size = read_u32_le(out, at).saturating_sub(old).saturating_add(new);
+    write_u32_le(out, at, size);
+}
+
+/// Size of the certificate table (data directory 4); 0 when unsigned.
+pub(crate) fn pe_certificate_table_size(exe: &[u8]) -> u32 {
+    let opt = pe_optional_header_offset(exe);
+    let magic = u16::from_le_bytes([exe[opt], exe[opt + 1]]);
+    let data_directories = opt + if magic == 0x20b { 112 } else { 96 };
+    exe.get(data_directories + 4 * 8 + 4..data_directories + 4 * 8 + 8)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .unwrap_or(0)
+}
+
+/// Whether the Mach-O image containing file offset `at` (the whole file, or
+/// the universal-binary slice around it) has an LC_CODE_SIGNATURE.
+pub(crate) fn macho_has_code_signature(exe: &[u8], at: usize) -> bool {
+    let signed = |macho: &MachO<'_>| {
+        macho
+            .load_commands
+            .iter()
+            .any(|lc| matches!(lc.command, CommandVariant::CodeSignature(_)))
+    };
+    match Mach::parse(exe) {
+        Ok(Mach::Binary(macho)) => signed(&macho),
+        Ok(Mach::Fat(multi)) => multi.iter_arches().enumerate().any(|(i, arch)| {
+            let Ok(arch) = arch else { return false };
+            let contains = (arch.offset as usize..arch.offset as usize + arch.size as usize).contains(&at);
+            contains && matches!(multi.get(i), Ok(SingleArch::MachO(m)) if signed(&m))
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::layout::Layout;
+    use crate::pack::GraphBuilder;
+
+    const PE_FILE_ALIGN: usize = 0x200;
+    const PE_SECTION_ALIGN: usize = 0x1000;
+    /// Optional header of the synthetic PE (PE32+, 240 bytes).
+    const PE_OPT: usize = 0x58;
+
+    /// Minimal PE32+ with a `.text` section and, if `bun` is set, a last
+    /// `.bun` section holding it length-prefixed, as `bun build --compile` adds it.
+    pub(crate) fn synthetic_pe(bun: Option<&[u8]>) -> Vec<u8> {
+        let mut exe = vec![0u8; 2 * PE_FILE_ALIGN];
+        exe[..2].copy_from_slice(b"MZ");
+        write_u32_le(&mut exe, 0x3c, 0x40);
+        exe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        exe[0x44..0x46].copy_from_slice(&0x8664u16.to_le_bytes());
+        exe[0x46..0x48].copy_from_slice(&1u16.to_le_bytes());
+        exe[0x54..0x56].copy_from_slice(&240u16.to_le_bytes());
+        exe[0x56..0x58].copy_from_slice(&0x22u16.to_le_bytes());
+        exe[PE_OPT..PE_OPT + 2].copy_from_slice(&0x20bu16.to_le_bytes());
+        write_u32_le(&mut exe, PE_OPT + 4, PE_FILE_ALIGN as u32);
+        write_u32_le(&mut exe, PE_OPT + 16, 0x1000);
+        write_u32_le(&mut exe, PE_OPT + 20, 0x1000);
+        exe[PE_OPT + 24..PE_OPT + 32].copy_from_slice(&0x1_4000_0000u64.to_le_bytes());
+        write_u32_le(&mut exe, PE_OPT + 32, PE_SECTION_ALIGN as u32);
+        write_u32_le(&mut exe, PE_OPT + 36, PE_FILE_ALIGN as u32);
+        write_u32_le(&mut exe, PE_OPT + 56, 0x2000);
+        write_u32_le(&mut exe, PE_OPT + 60, PE_FILE_ALIGN as u32);
+        exe[PE_OPT + 68..PE_OPT + 70].copy_from_slice(&3u16.to_le_bytes());
+        write_u32_le(&mut exe, PE_OPT + 108, 16);
+        let text = PE_OPT + 240;
+        exe[text..text + 8].copy_from_slice(b".text\0\0\0");
+        write_u32_le(&mut exe, text + 8, 0x10);
+        write_u32_le(&mut exe, text + 12, 0x1000);
+        write_u32_le(&mut exe, text + 16, PE_FILE_ALIGN as u32);
+        write_u32_le(&mut exe, text + 20, PE_FILE_ALIGN as u32);
+        write_u32_le(&mut exe, text + 36, 0x6000_0020);
+        exe[PE_FILE_ALIGN] = 0xc3;
+
+        if let Some(blob) = bun {
+            let payload = section_payload(blob, true);
+            let raw_size = payload.len().next_multiple_of(PE_FILE_ALIGN);
+            let header = text + PE_SECTION_HEADER_SIZE;
+            exe[0x46..0x48].copy_from_slice(&2u16.to_le_bytes());
+            exe[header..header + 8].copy_from_slice(b".bun\0\0\0\0");
+            write_u32_le(&mut exe, header + 8, payload.len() as u32);
+            write_u32_le(&mut exe, header + 12, 0x2000);
+            write_u32_le(&mut exe, header + 16, raw_size as u32);
+            let start = exe.len();
+            write_u32_le(&mut exe, header + 20, start as u32);
+            write_u32_le(&mut exe, header + 36, IMAGE_SCN_CNT_INITIALIZED_DATA | 0x4000_0000);
+            write_u32_le(&mut exe, PE_OPT + 8, raw_size as u32);
+            write_u32_le(&mut exe, PE_OPT + 56, (0x2000 + payload.len()).next_multiple_of(PE_SECTION_ALIGN) as u32);
+            exe.extend_from_slice(&payload);
+            exe.resize(start + raw_size, 0);
+        }
+        exe
+    }
+
+    /// Minimal x86_64 Mach-O whose only load command is a `__BUN` segment with
+    /// a `__bun` section of `size` bytes holding `blob` length-prefixed.
+    pub(crate) fn synthetic_macho(blob: &[u8], size: usize) -> Vec<u8> {
+        const DATA: usize = 0x100;
+        let mut exe = vec![0u8; DATA];
+        let u32_at = |exe: &mut Vec<u8>, at: usize, v: u32| write_u32_le(exe, at, v);
+        let u64_at = |exe: &mut Vec<u8>, at: usize, v: u64| exe[at..at + 8].copy_from_slice(&v.to_le_bytes());
+        u32_at(&mut exe, 0, 0xfeed_facf);
+        u32_at(&mut exe, 4, 0x0100_0007);
+        u32_at(&mut exe, 8, 3);
+        u32_at(&mut exe, 12, 2);
+        u32_at(&mut exe, 16, 1);
+        u32_at(&mut exe, 20, 72 + 80);
+        let seg = 32;
+        u32_at(&mut exe, seg, 0x19);
+        u32_at(&mut exe, seg + 4, 72 + 80);
+        exe[seg + 8..seg + 13].copy_from_slice(b"__BUN");
+        u64_at(&mut exe, seg + 24, 0x1_0000_0000);
+        u64_at(&mut exe, seg + 32, size as u64);
+        u64_at(&mut exe, seg + 40, DATA as u64);
+        u64_at(&mut exe, seg + 48, size as u64);
+        u32_at(&mut exe, seg + 56, 1);
+        u32_at(&mut exe, seg + 60, 1);
+        u32_at(&mut exe, seg + 64, 1);
+        let sect = seg + 72;
+        exe[sect..sect + 5].copy_from_slice(b"__bun");
+        exe[sect + 16..sect + 21].copy_from_slice(b"__BUN");
+        u64_at(&mut exe, sect + 32, 0x1_0000_0000);
+        u64_at(&mut exe, sect + 40, size as u64);
+        u32_at(&mut exe, sect + 48, DATA as u32);
+        let payload = section_payload(blob, true);
+        exe.extend_from_slice(&payload);
+        exe.resize(DATA + size, 0);
+        exe
+    }
+
+    /// `sample_blob` with its first module's contents grown to `len` bytes.
+    pub(crate) fn grown_blob(len: usize) -> Vec<u8> {
+        let blob = sample_blob(Layout::current());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.modules[0].contents.data = vec![b'x'; len].into();
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn pe_in_place() {
+        let exe = synthetic_pe(Some(&grown_blob(600)));
+        let blob = sample_blob(Layout::current());
+        let injected = inject_blob(&exe, &blob, None).unwrap();
+        assert_eq!(injected.bytes.len(), exe.len());
+        assert_eq!(&injected.bytes[injected.location.range.clone()], &blob[..]);
+        assert_eq!(read_u32_le(&injected.bytes, PE_OPT + 8), read_u32_le(&exe, PE_OPT + 8));
+        assert!(injected.warnings.is_empty());
+    }
+
+    #[test]
+    fn pe_grow_updates_sizes() {
+        let exe = synthetic_pe(Some(&sample_blob(Layout::current())));
+        let blob = grown_blob(5000);
+        let injected = inject_blob(&exe, &blob, None).unwrap();
+        let Container::PeSection { index, raw, .. } = &injected.location.container else {
+            panic!("expected a PE section");
+        };
+        assert_eq!(*index, 1);
+        assert_eq!(raw.len(), (blob.len() + 8).next_multiple_of(PE_FILE_ALIGN));
+        assert_eq!(injected.bytes.len(), raw.end);
+        assert_eq!(read_u32_le(&injected.bytes, PE_OPT + 8) as usize, raw.len());
+        assert_eq!(
+            read_u32_le(&injected.bytes, PE_OPT + 56) as usize,
+            (0x2000 + blob.len() + 8).next_multiple_of(PE_SECTION_ALIGN)
+        );
+        // Same headers as a PE built around the grown blob to begin with.
+        assert_eq!(injected.bytes, synthetic_pe(Some(&blob)));
+    }
+
+    #[test]
+    fn pe_grow_refused_when_data_follows() {
+        let mut exe = synthetic_pe(Some(&sample_blob(Layout::current())));
+        exe.extend_from_slice(&[1; 16]);
+        let err = inject_blob(&exe, &grown_blob(5000), None).unwrap_err().to_string();
+        assert!(err.contains("16 bytes follow it"), "{}", err);
+    }
+
+    #[test]
+    fn macho_in_place_and_refuses_to_grow() {
+        let exe = synthetic_macho(&sample_blob(Layout::current()), 0x400);
+        let blob = grown_blob(400);
+        let injected = inject_blob(&exe, &blob, None).unwrap();
+        assert_eq!(injected.bytes.len(), exe.len());
+        assert_eq!(&injected.bytes[injected.location.range.clone()], &blob[..]);
+        assert_eq!(injected.location.arch.as_deref(), Some("x86_64"));
+
+        let err = inject_blob(&exe, &grown_blob(5000), None).unwrap_err().to_string();
+        assert!(err.contains("does not fit"), "{}", err);
+    }
+}
@@ -0,0 +1,146 @@
+//! Per-module scalar fields of CompiledModuleGraphFile: encoding, loader,
+//! module_format and side.
+//!
+//! **Interaction**: Decoded by `ModuleGraph::parse` from the four trailing bytes
+//! of each module entry; reported by `unpack` in the manifest. Values mirror the
+//! `enum(u8)` declarations in StandaloneModuleGraph.zig and options.zig. Bytes
+//! not known to this tool are kept as `Unknown(u8)` rather than rejected.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Declares a `u8`-backed enum with `from_u8` / `as_u8` / `as_str` and an
+/// `Unknown(u8)` fallback for values added by newer Bun versions. Serialized
+/// as its display string (`"js"`, `"unknown(42)"`), which `FromStr` accepts back.
+macro_rules! byte_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident = $value:literal => $label:literal,)+ }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)+
+            Unknown(u8),
+        }
+
+        impl $name {
+            pub fn from_u8(b: u8) -> Self {
+                match b {
+                    $($value => Self::$variant,)+
+                    other => Self::Unknown(other),
+                }
+            }
+
+            pub fn as_u8(self) -> u8 {
+                match self {
+                    $(Self::$variant => $value,)+
+                    Self::Unknown(other) => other,
+                }
+            }
+
+            /// Lowercase name as spelled in the Zig source; `unknown` for `Unknown`.
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $label,)+
+                    Self::Unknown(_) => "unknown",
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    Self::Unknown(b) => write!(f, "unknown({})", b),
+                    other => f.write_str(other.as_str()),
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($label => Ok(Self::$variant),)+
+                    other => other
+                        .strip_prefix("unknown(")
+                        .and_then(|rest| rest.strip_suffix(')'))
+                        .and_then(|n| n.parse::<u8>().ok())
+                        .map(Self::Unknown)
+                        .ok_or_else(|| format!("invalid {} {:?}", stringify!($name), other)),
+                }
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+byte_enum! {
+    /// `CompiledModuleGraphFile.encoding`: how `contents` is stored.
+    Encoding {
+        Binary = 0 => "binary",
+        Latin1 = 1 => "latin1",
+        Utf8 = 2 => "utf8",
+    }
+}
+
+byte_enum! {
+    /// `bun.options.Loader`: which loader Bun used for the module.
+    Loader {
+        Jsx = 0 => "jsx",
+        Js = 1 => "js",
+        Ts = 2 => "ts",
+        Tsx = 3 => "tsx",
+        Css = 4 => "css",
+        File = 5 => "file",
+        Json = 6 => "json",
+        Jsonc = 7 => "jsonc",
+        Toml = 8 => "toml",
+        Wasm = 9 => "wasm",
+        Napi = 10 => "napi",
+        Base64 = 11 => "base64",
+        Dataurl = 12 => "dataurl",
+        Text = 13 => "text",
+        Bunsh = 14 => "bunsh",
+        Sqlite = 15 => "sqlite",
+        SqliteEmbedded = 16 => "sqlite_embedded",
+        Html = 17 => "html",
+        Yaml = 18 => "yaml",
+    }
+}
+
+impl Loader {
+    /// JavaScript or TypeScript source (what `diff` shows as text).
+    pub fn is_javascript(self) -> bool {
+        matches!(self, Self::Js | Self::Jsx | Self::Ts | Self::Tsx)
+    }
+}
+
+byte_enum! {
+    /// `ModuleFormat`: whether the module is ESM, CommonJS or neither (assets).
+    ModuleFormat {
+        None = 0 => "none",
+        Esm = 1 => "esm",
+        Cjs = 2 => "cjs",
+    }
+}
+
+byte_enum! {
+    /// `FileSide`: server (runtime) or client (HTML-imported browser bundle).
+    FileSide {
+        Server = 0 => "server",
+        Client = 1 => "client",
+    }
+}
@@ -0,0 +1,203 @@
+//! Known StandaloneModuleGraph layouts, keyed by the Bun versions that wrote them.
+//!
+//! **Interaction**: `ModuleGraph::parse` tries every entry of [`LAYOUTS`] (newest
+//! first) and keeps the first one whose parse passes the invariant checks;
+//! `ModuleGraph::parse_with_hint` tries the layout of the detected runtime
+//! version first; `ModuleGraph::parse_with_layout` forces one (CLI `--layout`). `GraphBuilder`
+//! writes the module table and Offsets with the layout the graph was read with,
+//! so older blobs round-trip unchanged. Version ranges are approximate; the
+//! field sizes are what decides a match.
+
+use crate::graph::{read_u32_le, read_u64_le, Offsets, StringPointer, MODULE_STRUCT_SIZE, OFFSETS_SIZE};
+use crate::runtime::BunVersion;
+
+/// Sizes and optional fields of one on-disk layout.
+///
+/// A module entry is 3 or 4 StringPointers (name, contents, sourcemap, and
+/// bytecode when `has_bytecode`) followed by one byte each for encoding, loader,
+/// then module_format and side when present, padded to `module_size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Name accepted by `--layout` and recorded in `manifest.json`.
+    pub name: &'static str,
+    /// Bun releases known to write this layout.
+    pub bun_versions: &'static str,
+    /// First Bun release that writes it (see `BunVersion::layout`).
+    pub since: BunVersion,
+    /// Encoded size of Offsets (before the trailer).
+    pub offsets_size: usize,
+    /// Size of one module table entry.
+    pub module_size: usize,
+    /// Offsets carries `compile_exec_argv_ptr` and `flags`.
+    pub has_exec_argv: bool,
+    pub has_bytecode: bool,
+    pub has_module_format: bool,
+    pub has_side: bool,
+}
+
+/// Known layouts, newest first. Detection picks the first one that validates.
+pub const LAYOUTS: &[Layout] = &[
+    Layout {
+        name: "v3",
+        bun_versions: ">= 1.2.16",
+        since: BunVersion::new(1, 2, 16),
+        offsets_size: OFFSETS_SIZE,
+        module_size: MODULE_STRUCT_SIZE,
+        has_exec_argv: true,
+        has_bytecode: true,
+        has_module_format: true,
+        has_side: true,
+    },
+    Layout {
+        name: "v2",
+        bun_versions: "1.1.30 - 1.2.15",
+        since: BunVersion::new(1, 1, 30),
+        offsets_size: 24,
+        module_size: 36,
+        has_exec_argv: false,
+        has_bytecode: true,
+        has_module_format: true,
+        has_side: false,
+    },
+    Layout {
+        name: "v1",
+        bun_versions: "1.0.0 - 1.1.29",
+        since: BunVersion::new(1, 0, 0),
+        offsets_size: 24,
+        module_size: 28,
+        has_exec_argv: false,
+        has_bytecode: false,
+        has_module_format: false,
+        has_side: false,
+    },
+];
+
+impl Layout {
+    /// Layout written by current Bun releases (and by `pack` for new blobs).
+    pub fn current() -> &'static Layout {
+        &LAYOUTS[0]
+    }
+
+    /// Looks up a layout by its `name`.
+    pub fn by_name(name: &str) -> Option<&'static Layout> {
+        LAYOUTS.iter().find(|l| l.name == name)
+    }
+
+    /// Number of StringPointers at the start of a module entry.
+    pub fn pointer_count(&self) -> usize {
+        if self.has_bytecode {
+            4
+        } else {
+            3
+        }
+    }
+
+    /// Decodes `offsets_size` bytes at `at`. Fields the layout lacks are zero.
+    pub fn decode_offsets(&self, bytes: &[u8], at: usize) -> Option<Offsets> {
+        if self.has_exec_argv {
+            return Offsets::decode(bytes, at);
+        }
+        let b = bytes.get(at..)?.get(..self.offsets_size)?;
+        Some(Offsets {
+            byte_count: read_u64_le(b, 0),
+            modules_ptr_offset: read_u32_le(b, 8),
+            modules_ptr_length: read_u32_le(b, 12),
+            entry_point_id: read_u32_le(b, 16),
+            ..Offsets::default()
+        })
+    }
+
+    /// Encodes Offsets in this layout; trailing padding is zero.
+    pub fn encode_offsets(&self, offsets: &Offsets) -> Vec<u8> {
+        let mut out = offsets.encode().to_vec();
+        out.resize(self.offsets_size, 0);
+        out
+    }
+
+    /// Reads the StringPointers of one module entry; bytecode is (0, 0) if absent.
+    pub(crate) fn read_pointers(&self, entry: &[u8]) -> [StringPointer; 4] {
+        let mut ptrs = [StringPointer::default(); 4];
+        for (i, p) in ptrs.iter_mut().enumerate().take(self.pointer_count()) {
+            *p = StringPointer::read(entry, i * 8);
+        }
+        ptrs
+    }
+
+    /// Reads encoding, loader, module_format and side bytes; absent ones are 0.
+    pub(crate) fn read_kinds(&self, entry: &[u8]) -> [u8; 4] {
+        let at = self.pointer_count() * 8;
+        [
+            entry[at],
+            entry[at + 1],
+            if self.has_module_format { entry[at + 2] } else { 0 },
+            if self.has_side { entry[at + 3] } else { 0 },
+        ]
+    }
+
+    /// Writes one module entry into `entry` (`module_size` bytes, zeroed).
+    pub(crate) fn write_entry(&self, entry: &mut [u8], ptrs: &[StringPointer], kinds: [u8; 4]) {
+        for (i, p) in ptrs.iter().take(self.pointer_count()).enumerate() {
+            entry[i * 8..i * 8 + 4].copy_from_slice(&p.offset.to_le_bytes());
+            entry[i * 8 + 4..i * 8 + 8].copy_from_slice(&p.length.to_le_bytes());
+        }
+        let at = self.pointer_count() * 8;
+        entry[at] = kinds[0];
+        entry[at + 1] = kinds[1];
+        if self.has_module_format {
+            entry[at + 2] = kinds[2];
+        }
+        if self.has_side {
+            entry[at + 3] = kinds[3];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_round_trips_and_drops_missing_fields() {
+        let ptrs = [
+            StringPointer { offset: 1, length: 2 },
+            StringPointer { offset: 3, length: 4 },
+            StringPointer { offset: 5, length: 6 },
+            StringPointer { offset: 7, length: 8 },
+        ];
+        for layout in LAYOUTS {
+            let mut entry = vec![0u8; layout.module_size];
+            layout.write_entry(&mut entry, &ptrs, [1, 2, 3, 4]);
+            let mut want = ptrs;
+            if !layout.has_bytecode {
+                want[3] = StringPointer::default();
+            }
+            assert_eq!(layout.read_pointers(&entry), want, "layout {}", layout.name);
+            let kinds = [1, 2, if layout.has_module_format { 3 } else { 0 }, if layout.has_side { 4 } else { 0 }];
+            assert_eq!(layout.read_kinds(&entry), kinds, "layout {}", layout.name);
+        }
+    }
+
+    #[test]
+    fn offsets_without_exec_argv() {
+        let v2 = Layout::by_name("v2").unwrap();
+        let off = Offsets {
+            byte_count: 100,
+            modules_ptr_offset: 40,
+            modules_ptr_length: 72,
+            entry_point_id: 1,
+            ..Offsets::default()
+        };
+        let encoded = v2.encode_offsets(&off);
+        assert_eq!(encoded.len(), v2.offsets_size);
+        assert_eq!(&encoded[20..], [0; 4]);
+        assert_eq!(v2.decode_offsets(&encoded, 0), Some(off));
+    }
+
+    #[test]
+    fn layouts_are_newest_first() {
+        assert_eq!(Layout::current().name, "v3");
+        assert!(LAYOUTS.windows(2).all(|w| w[0].since > w[1].since));
+        assert_eq!(Layout::by_name("v1").map(|l| l.module_size), Some(28));
+        assert_eq!(Layout::by_name("v9"), None);
+    }
+}
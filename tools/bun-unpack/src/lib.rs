@@ -0,0 +1,72 @@
+//! Unpack .bun section from Bun standalone executables.
+//!
+//! Parses StandaloneModuleGraph format (trailer, Offsets, CompiledModuleGraphFile[])
+//! into a typed [`ModuleGraph`] that borrows from the blob. Writing files to an
+//! output directory is one consumer of that API (see [`unpack`]).
+//! See docs/bun/compile-principle.md and docs/bun/decompile-tooling.md.
+
+pub mod assets;
+pub mod batch;
+pub mod bundled;
+pub mod bytecode;
+pub mod diff;
+pub mod error;
+pub mod extract;
+pub mod filter;
+pub mod graph;
+pub mod inject;
+pub mod kind;
+pub mod layout;
+pub mod list;
+pub mod manifest;
+pub mod pack;
+pub mod runtime;
+pub mod scan;
+pub mod sourcemap;
+pub mod sources;
+pub mod store;
+pub mod strip;
+pub mod symbolicate;
+pub mod unpack;
+pub mod verify;
+
+pub use assets::{is_asset, is_asset_with, sniff_asset, AssetInfo, MAGIC};
+pub use batch::{
+    collect_inputs, format_batch_report, unpack_batch, BatchEntry, BatchReport, BatchStatus, BATCH_REPORT,
+};
+pub use bundled::{split_bundle, write_bundled, BundleSegment, BundledEntry, Wrapper, BUNDLED_DIR};
+pub use bytecode::{
+    format_bytecode_report, inspect_bytecode, BytecodeReport, CacheHeader, Segment, SourceLink, CACHE_HEADER_SIZE,
+};
+pub use diff::{
+    diff_graphs, format_diff, Change, DiffOptions, GraphDiff, KindChange, ModuleChange, ModuleSummary,
+    PartChange,
+};
+pub use error::{LayoutAttempt, ParseError, SourceMapError};
+pub use extract::{
+    extract_bun_blob, locate_all_bun_blobs, locate_bun_blob, macho_arch_name, map_file, BlobLocation,
+    Container,
+};
+pub use filter::ModuleFilter;
+pub use graph::{
+    Module, ModuleGraph, ModulePointers, Offsets, StringPointer, BUN_TRAILER, FLAG_NAMES,
+    MODULE_STRUCT_SIZE, OFFSETS_SIZE,
+};
+pub use inject::{inject_blob, Injected};
+pub use kind::{Encoding, FileSide, Loader, ModuleFormat};
+pub use layout::{Layout, LAYOUTS};
+pub use list::format_module_table;
+pub use manifest::{sha256_hex, ExecutableFormat, Manifest, ModuleEntry, PartEntry, SourceInfo};
+pub use pack::{pack_dir, GraphBuilder, PackModule, PackPart};
+pub use runtime::{detect_runtime, parse_for_runtime, BunVersion, RuntimeInfo, NEWEST_KNOWN_BUN};
+pub use scan::{format_scan_table, scan_graphs, ScanHit, ScanStatus};
+pub use sourcemap::{decode_mappings, Mapping, SerializedSourceMap, SourceMap};
+pub use sources::{normalize_source_path, write_sources, SourceEntry, SOURCES_DIR};
+pub use store::{format_store_refs, Store, StoreAdd, StoreRef, BUILDS_DIR, OBJECTS_DIR, REFS_DIR};
+pub use strip::{strip_blob, StripMethod, Stripped};
+pub use symbolicate::{OriginalPosition, Symbolicator};
+pub use unpack::{
+    module_rel_path, name_to_rel_path, unpack_blob, write_module_graph, write_modules, write_selected_modules,
+    UnpackOptions,
+};
+pub use verify::{format_verify_report, verify_blob, verify_graph, Issue, Region, VerifyReport};
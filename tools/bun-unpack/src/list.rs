@@ -0,0 +1,52 @@
+//! Module table for `list`: one line per module without writing any files.
+//!
+//! **Interaction**: Used by the CLI `list` subcommand. Shares the decoded flags
+//! and sizes with `manifest.txt`, but aligned into columns for terminals.
+
+use crate::graph::ModuleGraph;
+
+/// Formats `graph` as a header plus one row per module, then the compile-time
+/// settings. The entry point is marked with `*` after its index.
+pub fn format_module_table(graph: &ModuleGraph<'_>) -> Vec<String> {
+    let mut lines = Vec::with_capacity(graph.modules.len() + 4);
+    lines.push(format!(
+        "{:>5}  {:<7} {:<5} {:<6} {:<6} {:>10} {:>10} {:>10}  {}",
+        "#", "loader", "fmt", "enc", "side", "contents", "sourcemap", "bytecode", "name"
+    ));
+    for m in &graph.modules {
+        let marker = if m.index == graph.entry_point_id as usize { "*" } else { " " };
+        lines.push(format!(
+            "{:>4}{}  {:<7} {:<5} {:<6} {:<6} {:>10} {:>10} {:>10}  {}",
+            m.index,
+            marker,
+            m.loader.to_string(),
+            m.module_format.to_string(),
+            m.encoding.to_string(),
+            m.side.to_string(),
+            m.contents.len(),
+            m.sourcemap.len(),
+            m.bytecode.len(),
+            m.name_str()
+        ));
+    }
+    lines.push(format!(
+        "{} modules, entry point #{}",
+        graph.modules.len(),
+        graph.entry_point_id
+    ));
+    lines.push(format!("exec argv: {}", or_none(graph.compile_exec_argv().join(" "))));
+    lines.push(format!(
+        "flags: {:#x} {}",
+        graph.offsets.flags,
+        or_none(graph.offsets.flag_names().join(", "))
+    ));
+    lines
+}
+
+fn or_none(s: String) -> String {
+    if s.is_empty() {
+        "(none)".to_string()
+    } else {
+        s
+    }
+}
@@ -1,259 +1,905 @@
-//! Unpack .bun section from Bun standalone executables.
+//! CLI for bun-unpack: extract the .bun blob, parse it and write modules to a
+//! directory. Subcommands work on the same parsed graph: `list` / `cat` /
+//! `extract` inspect or select modules, `pack` rebuilds a blob from an unpacked
+//! directory, `inject` writes a blob back into an executable and `set-argv`
+//! rebuilds the graph with a new baked exec argv. `scan` finds graphs inside
+//! arbitrary files, `symbolicate` maps stack traces back to original sources,
+//! `diff` compares the graphs of two builds, `bytecode` checks JSC bytecode
+//! against its source, `verify` checks every pointer of a blob, `strip`
+//! removes the graph to recover the Bun runtime and `batch` unpacks a whole
+//! directory of executables in parallel. With `--store` the default mode and
+//! `batch` write into a content-addressed store, which `query` searches.
 //!
-//! Parses StandaloneModuleGraph format (trailer, Offsets, CompiledModuleGraphFile[])
-//! and writes each module's name/contents/sourcemap/bytecode to an output directory.
-//! See docs/bun/compile-principle.md and docs/bun/decompile-tooling.md.
+//! Parsing, writing and packing live in the `bun_unpack` library; see `lib.rs`.
 
 use std::fs;
+use std::io::{BufRead, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use goblin::Object;
+use clap::{Parser, Subcommand, ValueEnum};
+use memmap2::Mmap;
 
-const BUN_TRAILER: &[u8] = b"\n---- Bun! ----\n"; // 16 bytes
+use bun_unpack::{
+    collect_inputs, detect_runtime, diff_graphs, extract_bun_blob, format_batch_report, format_bytecode_report,
+    format_diff, format_module_table, format_scan_table, format_store_refs, format_verify_report,
+    inject_blob, inspect_bytecode, is_asset_with, locate_all_bun_blobs, map_file, pack_dir,
+    parse_for_runtime, scan_graphs, sha256_hex, strip_blob, unpack_batch, verify_blob,
+    write_module_graph, write_modules, DiffOptions, ExecutableFormat, GraphBuilder, Layout,
+    Manifest, ModuleFilter, ModuleGraph, RuntimeInfo, SourceInfo, Store, Symbolicator,
+    UnpackOptions, BATCH_REPORT, LAYOUTS,
+};
 
-/// Offsets struct at end of blob (Bun StandaloneModuleGraph.zig).
-/// All fields little-endian. StringPointer = offset (u32) + length (u32).
-#[repr(C)]
-struct Offsets {
-    byte_count: u64,           // usize on 64-bit
-    modules_ptr_offset: u32,
-    modules_ptr_length: u32,
-    entry_point_id: u32,
-    _compile_exec_argv_offset: u32,
-    _compile_exec_argv_length: u32,
-    flags: u32,
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let result = match args.command {
+        Some(Command::List(list)) => run_list(list),
+        Some(Command::Cat(cat)) => run_cat(cat),
+        Some(Command::Extract(extract)) => run_extract(extract),
+        Some(Command::Pack(pack)) => run_pack(pack),
+        Some(Command::Inject(inject)) => run_inject(inject),
+        Some(Command::SetArgv(set_argv)) => run_set_argv(set_argv),
+        Some(Command::Scan(scan)) => run_scan(scan),
+        Some(Command::Symbolicate(symbolicate)) => run_symbolicate(symbolicate),
+        Some(Command::Diff(diff)) => run_diff(diff),
+        Some(Command::Bytecode(bytecode)) => run_bytecode(bytecode),
+        Some(Command::Verify(verify)) => run_verify(verify),
+        Some(Command::Strip(strip)) => run_strip(strip),
+        Some(Command::Batch(batch)) => run_batch(batch),
+        Some(Command::Query(query)) => run_query(query),
+        None => run_unpack(args.unpack),
+    };
+    // Output piped into `head` and the like: a closed stdout ends the run quietly.
+    match result {
+        Err(e) if is_broken_pipe(&e) => Ok(()),
+        result => result,
+    }
 }
 
-/// CompiledModuleGraphFile (minimal layout for name/contents/sourcemap/bytecode).
-/// Schema.StringPointer = offset(u32) + length(u32). We use 36 bytes per module.
-const MODULE_STRUCT_SIZE: usize = 4 * 8 + 4; // 4×StringPointer + encoding + loader + module_format + side
+fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|io| io.kind() == std::io::ErrorKind::BrokenPipe)
+}
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let blob = if let Some(blob_path) = &args.blob {
-        fs::read(blob_path).with_context(|| format!("read blob {:?}", blob_path))?
+fn run_batch(args: BatchArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let store = args.store.as_deref().map(Store::open).transpose()?.map(|s| s.replace_builds(args.replace));
+    let out_dir = match (&store, &args.output) {
+        (Some(store), _) => store.root(),
+        (None, Some(output)) => output.as_path(),
+        (None, None) => anyhow::bail!("one of --output or --store is required"),
+    };
+    let (root, files) = collect_inputs(&args.input, Some(out_dir))?;
+    if files.is_empty() {
+        anyhow::bail!("no files match {:?}", args.input);
+    }
+    let report = unpack_batch(&root, &files, out_dir, store.as_ref(), &args.options.to_options(), args.jobs)?;
+    match args.format {
+        OutputFormat::Text => {
+            for line in format_batch_report(&report) {
+                writeln!(stdout, "{}", line)?;
+            }
+            writeln!(stdout, "Report written to {}", out_dir.join(BATCH_REPORT).display())?;
+        }
+        OutputFormat::Json => writeln!(stdout, "{}", serde_json::to_string_pretty(&report)?)?,
+    }
+    if report.failed > 0 {
+        anyhow::bail!("{} of {} files failed", report.failed, report.entries.len());
+    }
+    Ok(())
+}
+
+fn run_query(args: QueryArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let store = Store::open(&args.store)?;
+    let path = Path::new(&args.target);
+    let sha256 = if path.is_file() {
+        sha256_hex(&fs::read(path).with_context(|| format!("read {:?}", path))?)
     } else {
-        let exe_path = args.exe.as_deref().context("missing <EXE> when not using --blob")?;
-        extract_bun_blob(exe_path)?
+        args.target.to_ascii_lowercase()
     };
+    let refs = store.lookup(&sha256)?;
+    match args.format {
+        OutputFormat::Text => {
+            for line in format_store_refs(&sha256, &refs) {
+                writeln!(stdout, "{}", line)?;
+            }
+        }
+        OutputFormat::Json => writeln!(
+            stdout,
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "sha256": sha256, "refs": refs }))?
+        )?,
+    }
+    Ok(())
+}
+
+fn run_unpack(args: UnpackArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
     let out_dir = args
         .output
         .as_deref()
         .unwrap_or(Path::new("./unpacked"));
-    unpack_blob(&blob, out_dir)?;
-    println!("Unpacked to {}", out_dir.display());
+    // Build name in the store: --name, else the input's file name.
+    let name = match (&args.name, args.input.exe.as_ref().or(args.input.blob.as_ref())) {
+        (Some(name), _) => name.clone(),
+        (None, Some(path)) => path.file_name().map_or("build".into(), |n| n.to_string_lossy().into_owned()),
+        (None, None) => "build".to_string(),
+    };
+    // (source, mapping, blob range, output dir, build name): one job, or one per architecture with --all-arches.
+    let mut jobs = Vec::new();
+    if args.all_arches {
+        let exe_path = args.input.exe.as_deref().context("--all-arches requires <EXE>")?;
+        let map = Rc::new(map_file(exe_path)?);
+        for loc in locate_all_bun_blobs(&map)? {
+            let (dir, build) = match &loc.arch {
+                Some(arch) => (out_dir.join(arch), format!("{}/{}", name, arch)),
+                None => (out_dir.to_path_buf(), name.clone()),
+            };
+            jobs.push((SourceInfo::from_location(exe_path, &map, &loc), map.clone(), loc.range, dir, build));
+        }
+    } else {
+        let input = args.input.load_with_runtime()?;
+        jobs.push((input.source, Rc::new(input.map), input.range, out_dir.to_path_buf(), name));
+    }
+
+    let store = args.store.as_deref().map(Store::open).transpose()?.map(|s| s.replace_builds(args.replace));
+    let mut manifests = Vec::new();
+    for (source, map, range, dir, build) in jobs {
+        let graph = args.input.parse_graph(&map[range], &source)?;
+        if let Some(store) = &store {
+            let added = store.add_graph(&build, &graph, source)?;
+            if let OutputFormat::Text = args.format {
+                writeln!(
+                    stdout,
+                    "{} build {}: {} parts, {} new objects ({} bytes)",
+                    if added.replaced { "Replaced" } else { "Stored" },
+                    build,
+                    added.parts,
+                    added.new_objects,
+                    added.new_bytes
+                )?;
+            }
+            manifests.push(added.manifest);
+            continue;
+        }
+        manifests.push(write_modules(&graph, &dir, source, &args.options.to_options(), |_| true)?);
+        if let OutputFormat::Text = args.format {
+            writeln!(stdout, "Unpacked to {}", dir.display())?;
+        }
+    }
+    if let OutputFormat::Json = args.format {
+        if args.all_arches {
+            writeln!(stdout, "{}", serde_json::to_string_pretty(&manifests)?)?;
+        } else {
+            writeln!(stdout, "{}", serde_json::to_string_pretty(&manifests[0])?)?;
+        }
+    }
     Ok(())
 }
 
-#[derive(Parser)]
-#[command(name = "bun-unpack", about = "Unpack .bun section from Bun standalone executables")]
-struct Args {
-    /// Path to a Bun standalone executable (PE / ELF / Mach-O). Required unless --blob is set.
-    exe: Option<PathBuf>,
+fn run_list(args: ListArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let input = args.input.load_with_runtime()?;
+    let graph = args.input.parse_graph(input.blob(), &input.source)?;
+    match args.format {
+        OutputFormat::Text => {
+            for line in format_module_table(&graph) {
+                writeln!(stdout, "{}", line)?;
+            }
+            if let Some(runtime) = &input.source.runtime {
+                writeln!(stdout, "runtime: {}", runtime)?;
+            }
+        }
+        OutputFormat::Json => {
+            let manifest = Manifest::from_graph(&graph, input.source.clone());
+            writeln!(stdout, "{}", serde_json::to_string_pretty(&manifest)?)?;
+        }
+    }
+    Ok(())
+}
 
-    /// Parse a raw .bun blob file instead of an executable (e.g. previously extracted).
-    #[arg(long)]
-    blob: Option<PathBuf>,
+fn run_cat(args: CatArgs) -> Result<()> {
+    let input = args.input.load()?;
+    let graph = args.input.parse_graph(input.blob(), &input.source)?;
+    let module = graph
+        .find(&args.name)
+        .with_context(|| format!("no module matching {:?} (see `list`)", args.name))?;
+    let bytes = match args.part {
+        Part::Contents => module.contents,
+        Part::Sourcemap => module.sourcemap,
+        Part::Bytecode => module.bytecode,
+    };
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(bytes).context("write stdout")?;
+    stdout.flush().context("flush stdout")?;
+    Ok(())
+}
 
-    /// Output directory. Default: ./unpacked
-    #[arg(short, long)]
-    output: Option<PathBuf>,
+fn run_extract(args: ExtractArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let input = args.input.load_with_runtime()?;
+    let graph = args.input.parse_graph(input.blob(), &input.source)?;
+    let filter = ModuleFilter::new(&args.include, &args.exclude)?;
+    let mut options = args.options.to_options();
+    options.assets_only = args.assets_only;
+    let manifest = write_modules(&graph, &args.output, input.source.clone(), &options, |m| filter.matches(m))?;
+    let count = graph
+        .modules
+        .iter()
+        .filter(|m| filter.matches(m))
+        .filter(|m| !args.assets_only || is_asset_with(m, manifest.modules[m.index].asset.as_ref()))
+        .count();
+    writeln!(stdout, "Extracted {} of {} modules to {}", count, graph.modules.len(), args.output.display())?;
+    Ok(())
 }
 
-fn extract_bun_blob(exe_path: &Path) -> Result<Vec<u8>> {
-    let buf = fs::read(exe_path).with_context(|| format!("read exe {:?}", exe_path))?;
-    match Object::parse(&buf)? {
-        Object::PE(pe) => {
-            for section in &pe.sections {
-                let is_bun = section.name[..4] == *b".bun"
-                    && section.name[4..].iter().all(|&b| b == 0);
-                if is_bun {
-                    let start = section.pointer_to_raw_data as usize;
-                    let size = section.size_of_raw_data as usize;
-                    if start + size > buf.len() {
-                        anyhow::bail!(".bun section out of bounds (start={}, size={}, file_len={})", start, size, buf.len());
-                    }
-                    return Ok(buf[start..start + size].to_vec());
-                }
-            }
-            anyhow::bail!("no .bun section found in PE");
+fn run_pack(args: PackArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let (manifest, blob) = pack_dir(&args.dir)?;
+    // Re-parse so a layout bug surfaces here rather than at runtime.
+    ModuleGraph::parse(&blob).context("packed blob does not parse")?;
+    fs::write(&args.output, &blob).with_context(|| format!("write {:?}", args.output))?;
+    let identical = sha256_hex(&blob) == manifest.blob_sha256;
+    writeln!(
+        stdout,
+        "Packed {} modules to {} ({} bytes, {})",
+        manifest.modules.len(),
+        args.output.display(),
+        blob.len(),
+        if identical { "identical to original" } else { "modified" }
+    )?;
+    Ok(())
+}
+
+fn run_set_argv(args: SetArgvArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let input = args.input.load_with_runtime()?;
+    let graph = args.input.parse_graph(input.blob(), &input.source)?;
+    let old = graph.compile_exec_argv();
+    let mut builder = GraphBuilder::from_graph(&graph);
+    builder.set_compile_exec_argv(&args.argv);
+    let blob = builder.build()?;
+    ModuleGraph::parse(&blob).context("rebuilt blob does not parse")?;
+
+    if input.source.format == ExecutableFormat::Blob {
+        fs::write(&args.output, &blob).with_context(|| format!("write {:?}", args.output))?;
+    } else {
+        let injected = inject_blob(&input.map, &blob, args.input.arch.as_deref())?;
+        fs::write(&args.output, &injected.bytes).with_context(|| format!("write {:?}", args.output))?;
+        for w in &injected.warnings {
+            eprintln!("warning: {}", w);
         }
-        Object::Elf(elf) => {
-            // Linux: last 8 bytes = module graph length (u64 LE), preceding bytes = blob
-            if buf.len() < 8 {
-                anyhow::bail!("ELF too small for trailing length");
+    }
+    writeln!(
+        stdout,
+        "exec argv: {:?} -> {:?}; wrote {}",
+        old.join(" "),
+        args.argv.split_whitespace().collect::<Vec<_>>().join(" "),
+        args.output.display()
+    )?;
+    Ok(())
+}
+
+fn run_scan(args: ScanArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let map = map_file(&args.file)?;
+    let hits = scan_graphs(&map);
+    match args.format {
+        OutputFormat::Text => {
+            for line in format_scan_table(&hits) {
+                writeln!(stdout, "{}", line)?;
             }
-            let len_end = buf.len() - 8;
-            let len = u64::from_le_bytes(buf[len_end..].try_into().unwrap()) as usize;
-            if len == 0 || len_end < len {
-                anyhow::bail!("invalid module graph length at file end (len={}, file_len={})", len, buf.len());
+        }
+        OutputFormat::Json => writeln!(stdout, "{}", serde_json::to_string_pretty(&hits)?)?,
+    }
+
+    let selected: Vec<usize> = if args.unpack_all {
+        (0..hits.len()).filter(|&i| hits[i].range.is_some()).collect()
+    } else {
+        args.unpack
+    };
+    for i in selected {
+        let hit = hits.get(i).with_context(|| format!("no hit #{} ({} found)", i, hits.len()))?;
+        let (Some(graph), Some(range)) = (hit.graph(&map), &hit.range) else {
+            anyhow::bail!("hit #{} is not a valid graph: {}", i, hit.status);
+        };
+        let dir = args.output.join(format!("at-{:#x}", range.start));
+        let source = SourceInfo {
+            path: Some(args.file.display().to_string()),
+            format: ExecutableFormat::Blob,
+            arch: None,
+            runtime: None,
+        };
+        write_module_graph(&graph, &dir, source)?;
+        if let OutputFormat::Text = args.format {
+            writeln!(stdout, "Unpacked #{} to {}", i, dir.display())?;
+        }
+    }
+    Ok(())
+}
+
+fn run_symbolicate(args: SymbolicateArgs) -> Result<()> {
+    let input;
+    let graph;
+    let mut symbolicator = match &args.dir {
+        Some(dir) => Symbolicator::from_dir(dir)?,
+        None => {
+            input = args.input.load()?;
+            graph = args.input.parse_graph(input.blob(), &input.source)?;
+            Symbolicator::from_graph(&graph)
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.context("read stdin")?;
+        writeln!(stdout, "{}", symbolicator.symbolicate_line(&line)).context("write stdout")?;
+        for w in symbolicator.take_warnings() {
+            eprintln!("warning: {}", w);
+        }
+    }
+    stdout.flush().context("flush stdout")?;
+    Ok(())
+}
+
+fn run_diff(args: DiffArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let mut old_input = load_input(&args.old, args.blob, args.arch.as_deref())?;
+    let mut new_input = load_input(&args.new, args.blob, args.arch.as_deref())?;
+    old_input.detect_runtime();
+    new_input.detect_runtime();
+    let hint = |input: &Input| input.source.runtime.as_ref().and_then(RuntimeInfo::layout_hint);
+    let old = ModuleGraph::parse_with_hint(old_input.blob(), hint(&old_input))
+        .with_context(|| format!("parse {:?}", args.old))?;
+    let new = ModuleGraph::parse_with_hint(new_input.blob(), hint(&new_input))
+        .with_context(|| format!("parse {:?}", args.new))?;
+    let options = DiffOptions {
+        text_context: args.text_diff.then_some(args.context),
+    };
+    let diff = diff_graphs(&old, &new, &options);
+    match args.format {
+        OutputFormat::Text => {
+            for line in format_diff(&diff) {
+                writeln!(stdout, "{}", line)?;
             }
-            let start = len_end - len;
-            Ok(buf[start..len_end].to_vec())
         }
-        Object::Mach(mach) => {
-            // Mach-O: look for __bun or similar; Bun injects into a segment. Use heuristic:
-            // search for trailer in last 20MB and take from there
-            const TRAILER: &[u8] = b"\n---- Bun! ----\n";
-            let search_end = buf.len().min(20 * 1024 * 1024);
-            let search_start = buf.len().saturating_sub(search_end);
-            if let Some(pos) = buf[search_start..].windows(TRAILER.len()).position(|w| w == TRAILER) {
-                let trailer_pos = search_start + pos;
-                if trailer_pos < std::mem::size_of::<Offsets>() {
-                    anyhow::bail!("trailer too close to start for Offsets");
-                }
-                let offsets_start = trailer_pos - std::mem::size_of::<Offsets>();
-                let offsets_bytes = &buf[offsets_start..trailer_pos];
-                let byte_count = u64::from_le_bytes(offsets_bytes[0..8].try_into().unwrap()) as usize;
-                if byte_count > trailer_pos || byte_count == 0 {
-                    anyhow::bail!("invalid byte_count in Mach-O blob");
-                }
-                let blob_start = trailer_pos - byte_count;
-                return Ok(buf[blob_start..trailer_pos + TRAILER.len()].to_vec());
+        OutputFormat::Json => writeln!(stdout, "{}", serde_json::to_string_pretty(&diff)?)?,
+    }
+    Ok(())
+}
+
+fn run_bytecode(args: BytecodeArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let input = args.input.load()?;
+    let graph = args.input.parse_graph(input.blob(), &input.source)?;
+    let reports = inspect_bytecode(&graph);
+    match args.format {
+        OutputFormat::Text => {
+            for line in format_bytecode_report(&reports) {
+                writeln!(stdout, "{}", line)?;
             }
-            anyhow::bail!("no Bun trailer found in Mach-O (trailer search in last 20MB)");
         }
-        _ => anyhow::bail!("unsupported executable format (use --blob with raw .bun)"),
+        OutputFormat::Json => writeln!(stdout, "{}", serde_json::to_string_pretty(&reports)?)?,
     }
+    Ok(())
 }
 
-fn unpack_blob(blob: &[u8], out_dir: &Path) -> Result<()> {
-    if blob.len() < BUN_TRAILER.len() + std::mem::size_of::<Offsets>() {
-        anyhow::bail!("blob too small for trailer + Offsets");
+fn run_verify(args: VerifyArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let input = args.input.load()?;
+    let report = verify_blob(input.blob(), args.input.layout)?;
+    match args.format {
+        OutputFormat::Text => {
+            for line in format_verify_report(&report, args.map) {
+                writeln!(stdout, "{}", line)?;
+            }
+        }
+        OutputFormat::Json => writeln!(stdout, "{}", serde_json::to_string_pretty(&report)?)?,
     }
-    let trailer_start = blob
-        .len()
-        .checked_sub(BUN_TRAILER.len())
-        .unwrap();
-    if &blob[trailer_start..] != BUN_TRAILER {
-        anyhow::bail!("invalid trailer (expected \"\\n---- Bun! ----\\n\" at end)");
+    if !report.issues.is_empty() {
+        anyhow::bail!("{} integrity issues in {}", report.issues.len(), input.source.path.as_deref().unwrap_or("blob"));
     }
-    let offsets_start = trailer_start
-        .checked_sub(std::mem::size_of::<Offsets>())
-        .context("blob too small for Offsets")?;
-    let off = unsafe {
-        &*(blob[offsets_start..].as_ptr() as *const Offsets)
+    Ok(())
+}
+
+fn run_inject(args: InjectArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let blob = match (&args.blob, &args.dir) {
+        (Some(path), _) => fs::read(path).with_context(|| format!("read blob {:?}", path))?,
+        (None, Some(dir)) => pack_dir(dir)?.1,
+        (None, None) => anyhow::bail!("one of --blob or --dir is required"),
     };
-    let byte_count = off.byte_count as usize;
-    if byte_count > blob.len() || byte_count == 0 {
-        anyhow::bail!("invalid Offsets.byte_count");
+    let exe = map_file(&args.exe)?;
+    let injected = inject_blob(&exe, &blob, args.arch.as_deref())?;
+    fs::write(&args.output, &injected.bytes).with_context(|| format!("write {:?}", args.output))?;
+    for w in &injected.warnings {
+        eprintln!("warning: {}", w);
     }
-    let modules_offset = off.modules_ptr_offset as usize;
-    let modules_len = off.modules_ptr_length as usize;
-    if modules_offset >= blob.len()
-        || modules_len > blob.len()
-        || modules_offset + modules_len > blob.len()
-    {
-        anyhow::bail!("invalid modules_ptr (offset={}, length={})", modules_offset, modules_len);
-    }
-    if modules_len % MODULE_STRUCT_SIZE != 0 {
-        anyhow::bail!(
-            "modules_ptr length {} not divisible by module struct size {}",
-            modules_len,
-            MODULE_STRUCT_SIZE
-        );
+    writeln!(
+        stdout,
+        "Injected {} byte blob into {} ({})",
+        blob.len(),
+        args.output.display(),
+        injected.location.format
+    )?;
+    Ok(())
+}
+
+fn run_strip(args: StripArgs) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let exe = map_file(&args.exe)?;
+    let stripped = strip_blob(&exe, args.arch.as_deref())?;
+    fs::write(&args.output, &stripped.bytes).with_context(|| format!("write {:?}", args.output))?;
+    for w in &stripped.warnings {
+        eprintln!("warning: {}", w);
     }
-    let num_modules = modules_len / MODULE_STRUCT_SIZE;
-    fs::create_dir_all(out_dir).with_context(|| format!("create out dir {:?}", out_dir))?;
-
-    let mut manifest = Vec::new();
-    manifest.push(format!(
-        "entry_point_id={} (0-based index into modules)",
-        off.entry_point_id
-    ));
-    manifest.push(format!("modules_count={}", num_modules));
-    manifest.push(String::new());
-
-    for i in 0..num_modules {
-        let base = modules_offset + i * MODULE_STRUCT_SIZE;
-        let name_o = u32::from_le_bytes(blob[base..base + 4].try_into().unwrap()) as usize;
-        let name_l = u32::from_le_bytes(blob[base + 4..base + 8].try_into().unwrap()) as usize;
-        let contents_o = u32::from_le_bytes(blob[base + 8..base + 12].try_into().unwrap()) as usize;
-        let contents_l = u32::from_le_bytes(blob[base + 12..base + 16].try_into().unwrap()) as usize;
-        let sourcemap_o = u32::from_le_bytes(blob[base + 16..base + 20].try_into().unwrap()) as usize;
-        let sourcemap_l = u32::from_le_bytes(blob[base + 20..base + 24].try_into().unwrap()) as usize;
-        let bytecode_o = u32::from_le_bytes(blob[base + 24..base + 28].try_into().unwrap()) as usize;
-        let bytecode_l = u32::from_le_bytes(blob[base + 28..base + 32].try_into().unwrap()) as usize;
-
-        let name = slice_at(blob, name_o, name_l).unwrap_or_default();
-        let name_str = String::from_utf8_lossy(name);
-        let rel_path = name_to_rel_path(&name_str);
-        if rel_path.is_empty() {
-            continue;
-        }
-        let full = out_dir.join(&rel_path);
-        if let Some(p) = full.parent() {
-            fs::create_dir_all(p).with_context(|| format!("create dir {:?}", p))?;
-        }
+    writeln!(
+        stdout,
+        "Stripped {} byte blob ({}, {}) to {}: {} bytes, sha256 {}",
+        stripped.location.range.len(),
+        stripped.location.format,
+        stripped.method.as_str(),
+        args.output.display(),
+        stripped.bytes.len(),
+        sha256_hex(&stripped.bytes)
+    )?;
+    Ok(())
+}
 
-        let mut has_any = false;
-        if contents_l > 0 {
-            if let Some(b) = slice_at(blob, contents_o, contents_l) {
-                let p = full.as_path();
-                fs::write(p, b).with_context(|| format!("write {:?}", p))?;
-                has_any = true;
-                manifest.push(format!("[{}] {} (contents {} bytes)", i, rel_path, b.len()));
-            }
+#[derive(Parser)]
+#[command(
+    name = "bun-unpack",
+    about = "Unpack .bun section from Bun standalone executables",
+    args_conflicts_with_subcommands = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    unpack: UnpackArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the module table without writing files.
+    List(ListArgs),
+    /// Write one module's contents, sourcemap or bytecode to stdout.
+    Cat(CatArgs),
+    /// Unpack only modules matching --include / --exclude globs.
+    Extract(ExtractArgs),
+    /// Rebuild a raw .bun blob from an unpacked directory and its manifest.json.
+    Pack(PackArgs),
+    /// Replace the module graph inside an executable with a packed blob.
+    Inject(InjectArgs),
+    /// Rewrite the exec argv baked in at compile time (e.g. add --inspect).
+    SetArgv(SetArgvArgs),
+    /// Search any file (dump, installer, partial download) for module graphs.
+    Scan(ScanArgs),
+    /// Map /$bunfs/ frames of a stack trace on stdin to original sources.
+    Symbolicate(SymbolicateArgs),
+    /// Compare the module graphs of two builds: added, removed and changed modules.
+    Diff(DiffArgs),
+    /// Report JSC bytecode headers and check each against its source module.
+    Bytecode(BytecodeArgs),
+    /// Check every pointer of the graph and map blob coverage; exits non-zero on issues.
+    Verify(VerifyArgs),
+    /// Remove the module graph from an executable, leaving the bare Bun runtime.
+    Strip(StripArgs),
+    /// Unpack every executable under a directory or matching a glob, in parallel.
+    Batch(BatchArgs),
+    /// Find the builds in a --store that contain a file, by path or SHA-256.
+    Query(QueryArgs),
+}
+
+/// Where the module graph comes from: an executable or a raw blob.
+#[derive(clap::Args)]
+struct InputArgs {
+    /// Path to a Bun standalone executable (PE / ELF / Mach-O). Required unless --blob is set.
+    exe: Option<PathBuf>,
+
+    /// Parse a raw .bun blob file instead of an executable (e.g. previously extracted).
+    #[arg(long)]
+    blob: Option<PathBuf>,
+
+    /// Architecture to use from a universal (fat) Mach-O, e.g. arm64 or x86_64.
+    #[arg(long)]
+    arch: Option<String>,
+
+    /// Force a blob layout (v1, v2, v3) instead of detecting it.
+    #[arg(long, value_parser = parse_layout)]
+    layout: Option<&'static Layout>,
+}
+
+impl InputArgs {
+    /// Parses `blob` with the forced `--layout`, or detects it, trying the
+    /// layout of the runtime in `source` first if one was detected (see
+    /// `load_with_runtime`). Warns when that runtime is newer than the known
+    /// layouts or the blob has another layout.
+    fn parse_graph<'a>(&self, blob: &'a [u8], source: &SourceInfo) -> Result<ModuleGraph<'a>> {
+        if let Some(layout) = self.layout {
+            return Ok(ModuleGraph::parse_with_layout(blob, layout)?);
         }
-        if sourcemap_l > 0 {
-            if let Some(b) = slice_at(blob, sourcemap_o, sourcemap_l) {
-                let map_rel = format!("{}.map", rel_path);
-                let map_full = out_dir.join(&map_rel);
-                if let Some(parent) = map_full.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-                fs::write(&map_full, b).with_context(|| format!("write sourcemap {:?}", map_full))?;
-                has_any = true;
-                manifest.push(format!("  -> sourcemap {} ({} bytes)", map_rel, b.len()));
-            }
+        let (graph, warnings) = parse_for_runtime(blob, source.runtime.as_ref())?;
+        for w in &warnings {
+            eprintln!("warning: {}", w);
         }
-        if bytecode_l > 0 {
-            if let Some(b) = slice_at(blob, bytecode_o, bytecode_l) {
-                let jsc_rel = if rel_path.ends_with(".jsc") {
-                    rel_path.clone()
-                } else {
-                    format!("{}.jsc", rel_path)
-                };
-                let jsc_full = out_dir.join(&jsc_rel);
-                if let Some(parent) = jsc_full.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-                fs::write(&jsc_full, b).with_context(|| format!("write bytecode {:?}", jsc_full))?;
-                has_any = true;
-                manifest.push(format!("  -> bytecode {} ({} bytes, JSC)", jsc_rel, b.len()));
-            }
-        }
-        if !has_any {
-            manifest.push(format!("[{}] {} (no contents/sourcemap/bytecode)", i, rel_path));
+        Ok(graph)
+    }
+
+    /// Maps the input file and locates the blob in it.
+    fn load(&self) -> Result<Input> {
+        match (&self.blob, &self.exe) {
+            (Some(blob_path), _) => load_input(blob_path, true, None),
+            (None, Some(exe_path)) => load_input(exe_path, false, self.arch.as_deref()),
+            (None, None) => anyhow::bail!("missing <EXE> when not using --blob"),
         }
     }
 
-    fs::write(out_dir.join("manifest.txt"), manifest.join("\n"))
-        .context("write manifest.txt")?;
-    Ok(())
+    /// Like `load`, also detecting the runtime: for commands that report it
+    /// or write the graph back, where the layout hint matters.
+    fn load_with_runtime(&self) -> Result<Input> {
+        let mut input = self.load()?;
+        input.detect_runtime();
+        Ok(input)
+    }
 }
 
-fn slice_at(blob: &[u8], offset: usize, length: usize) -> Option<&[u8]> {
-    if length == 0 {
-        return Some(&[]);
+/// Maps `path` and locates the blob: the whole file if `is_blob`, otherwise the
+/// section / trailer of the executable (slice `arch` of a universal Mach-O).
+/// The runtime is not detected; see `Input::detect_runtime`.
+fn load_input(path: &Path, is_blob: bool, arch: Option<&str>) -> Result<Input> {
+    let map = map_file(path)?;
+    if is_blob {
+        let source = SourceInfo {
+            path: Some(path.display().to_string()),
+            format: ExecutableFormat::Blob,
+            arch: None,
+            runtime: None,
+        };
+        let range = 0..map.len();
+        return Ok(Input { source, map, range });
     }
-    blob.get(offset..).and_then(|s| s.get(..length))
-}
-
-fn name_to_rel_path(name: &str) -> String {
-    let s = name
-        .trim_start_matches("file:///")
-        .trim_start_matches("B:\\~BUN\\")
-        .trim_start_matches("B:/~BUN/")
-        .trim_start_matches("/$bunfs/");
-    let s = s.replace('\\', "/");
-    if s.contains("..") || s.starts_with('/') {
-        return String::new();
+    let (loc, _) = extract_bun_blob(&map, arch)?;
+    Ok(Input {
+        source: SourceInfo::without_runtime(path, &loc),
+        map,
+        range: loc.range,
+    })
+}
+
+fn parse_layout(name: &str) -> Result<&'static Layout, String> {
+    Layout::by_name(name).ok_or_else(|| {
+        let known: Vec<_> = LAYOUTS.iter().map(|l| format!("{} (bun {})", l.name, l.bun_versions)).collect();
+        format!("unknown layout; known: {}", known.join(", "))
+    })
+}
+
+/// A memory-mapped input and the blob's range inside it.
+struct Input {
+    source: SourceInfo,
+    map: Mmap,
+    range: Range<usize>,
+}
+
+impl Input {
+    fn blob(&self) -> &[u8] {
+        &self.map[self.range.clone()]
     }
-    if s.is_empty() {
-        return String::new();
+
+    /// Searches the executable outside the blob for the runtime and records it
+    /// in `source`. A `--blob` input has no runtime to search.
+    fn detect_runtime(&mut self) {
+        if self.source.format != ExecutableFormat::Blob {
+            self.source.runtime = detect_runtime(&self.map, self.range.clone());
+        }
     }
-    s
+}
+
+#[derive(clap::Args)]
+struct UnpackArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Output directory. Default: ./unpacked
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Unpack every architecture of a universal Mach-O into <OUTPUT>/<arch>/.
+    #[arg(long, conflicts_with_all = ["arch", "blob"])]
+    all_arches: bool,
+
+    /// Add the modules to a content-addressed store instead of writing <OUTPUT>.
+    #[arg(long, conflicts_with_all = ["output", "sources", "split_bundled"])]
+    store: Option<PathBuf>,
+
+    /// Build name in the store, a relative path (default: the file name; <NAME>/<arch> with --all-arches).
+    #[arg(long, requires = "store")]
+    name: Option<String>,
+
+    /// Replace a build of the same name already in the store instead of failing.
+    #[arg(long, requires = "store")]
+    replace: bool,
+
+    /// Stdout format: a one-line summary (text) or the full manifest (json).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    #[command(flatten)]
+    options: OptionArgs,
+}
+
+/// Extra outputs shared by the commands that write a module graph.
+#[derive(clap::Args)]
+struct OptionArgs {
+    /// Also write original sources from sourcemap sourcesContent to <OUTPUT>/sources/.
+    #[arg(long)]
+    sources: bool,
+
+    /// Also split bundled JS modules at their `// path` comments into <OUTPUT>/bundled/.
+    #[arg(long)]
+    split_bundled: bool,
+}
+
+impl OptionArgs {
+    fn to_options(&self) -> UnpackOptions {
+        UnpackOptions {
+            sources: self.sources,
+            bundled: self.split_bundled,
+            assets_only: false,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct ListArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Output format: aligned table (text) or the manifest (json).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(clap::Args)]
+struct CatArgs {
+    /// Module to print: raw name, relative path, or a unique path suffix.
+    name: String,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Which part of the module to print.
+    #[arg(long, value_enum, default_value_t = Part::Contents)]
+    part: Part,
+}
+
+#[derive(clap::Args)]
+struct ExtractArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Glob over relative paths to include (repeatable). Default: everything.
+    #[arg(long)]
+    include: Vec<String>,
+
+    /// Glob over relative paths to exclude (repeatable).
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// Only modules that are assets: non-JS loaders (file, napi, wasm, ...) or
+    /// recognized binary contents (native addons, images, ...).
+    #[arg(long)]
+    assets_only: bool,
+
+    /// Output directory.
+    #[arg(short, long, default_value = "./unpacked")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    options: OptionArgs,
+}
+
+#[derive(clap::Args)]
+struct PackArgs {
+    /// Directory produced by unpacking (must contain manifest.json).
+    dir: PathBuf,
+
+    /// Output blob path.
+    #[arg(short, long, default_value = "packed.bun")]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct InjectArgs {
+    /// Bun standalone executable to patch (PE / ELF / Mach-O).
+    exe: PathBuf,
+
+    /// Raw .bun blob to inject (e.g. from `pack`).
+    #[arg(long, conflicts_with = "dir")]
+    blob: Option<PathBuf>,
+
+    /// Unpacked directory to pack and inject in one step.
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Architecture slice to patch in a universal (fat) Mach-O.
+    #[arg(long)]
+    arch: Option<String>,
+
+    /// Output executable path (the input is never modified).
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct SetArgvArgs {
+    /// New exec argv as one string, e.g. "--smol --inspect"; "" clears it.
+    #[arg(long, allow_hyphen_values = true)]
+    argv: String,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Output path: a patched executable, or a blob with --blob (the input is never modified).
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct ScanArgs {
+    /// File to search for Bun trailers.
+    file: PathBuf,
+
+    /// Unpack the graph of this hit (index from the table); repeatable.
+    #[arg(long, value_name = "INDEX")]
+    unpack: Vec<usize>,
+
+    /// Unpack every valid graph found.
+    #[arg(long, conflicts_with = "unpack")]
+    unpack_all: bool,
+
+    /// Output directory; each graph goes to <DIR>/at-<start offset>/.
+    #[arg(short, long, default_value = "./unpacked")]
+    output: PathBuf,
+
+    /// Output format of the hit table.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(clap::Args)]
+struct SymbolicateArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Use the sourcemaps of an unpacked directory (with manifest.json) instead of an executable.
+    #[arg(long, conflicts_with_all = ["exe", "blob", "arch", "layout"])]
+    dir: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct DiffArgs {
+    /// Old build: executable, or raw blob with --blob.
+    old: PathBuf,
+
+    /// New build: executable, or raw blob with --blob.
+    new: PathBuf,
+
+    /// Both inputs are raw .bun blobs instead of executables.
+    #[arg(long)]
+    blob: bool,
+
+    /// Architecture to use from universal (fat) Mach-O inputs.
+    #[arg(long)]
+    arch: Option<String>,
+
+    /// Append a unified diff of every changed JavaScript module.
+    #[arg(long)]
+    text_diff: bool,
+
+    /// Context lines around each hunk of --text-diff.
+    #[arg(long, default_value_t = 3, requires = "text_diff")]
+    context: usize,
+
+    /// Output format: change list (text) or the full diff (json).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(clap::Args)]
+struct BytecodeArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Output format: one block per module (text) or the reports (json).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(clap::Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Print the full coverage map (every referenced and unreferenced range).
+    #[arg(long)]
+    map: bool,
+
+    /// Output format: summary and issues (text) or the full report (json).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(clap::Args)]
+struct StripArgs {
+    /// Bun standalone executable to strip (PE / ELF / Mach-O).
+    exe: PathBuf,
+
+    /// Architecture slice to strip in a universal (fat) Mach-O.
+    #[arg(long)]
+    arch: Option<String>,
+
+    /// Output path for the runtime (the input is never modified).
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct BatchArgs {
+    /// Directory (searched recursively), file, or glob such as 'releases/**/*.exe'.
+    input: String,
+
+    /// Output directory; each executable goes to <OUTPUT>/<path relative to the input>/.
+    #[arg(short, long, required_unless_present = "store")]
+    output: Option<PathBuf>,
+
+    /// Add each executable to a content-addressed store as build <path relative to the input>.
+    #[arg(long, conflicts_with_all = ["output", "sources", "split_bundled"])]
+    store: Option<PathBuf>,
+
+    /// Replace builds of the same name already in the store instead of failing those files.
+    #[arg(long, requires = "store")]
+    replace: bool,
+
+    /// Number of parallel workers (default: one per CPU).
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    #[command(flatten)]
+    options: OptionArgs,
+
+    /// Output format of the summary: table (text) or the report (json).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(clap::Args)]
+struct QueryArgs {
+    /// SHA-256 of a module part, or a file whose contents to look up.
+    target: String,
+
+    /// Store directory written by --store.
+    #[arg(long)]
+    store: PathBuf,
+
+    /// Output format: one row per occurrence (text) or the refs (json).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Part {
+    Contents,
+    Sourcemap,
+    Bytecode,
 }
@@ -0,0 +1,268 @@
+//! Machine-readable `manifest.json`: Offsets, entry point, blob size, source
+//! executable format and per-module parts with sizes and SHA-256.
+//!
+//! **Interaction**: Built from a `ModuleGraph` by `Manifest::from_graph`; written
+//! by `unpack::write_module_graph` next to `manifest.txt` and printed by the CLI
+//! in `--format json` mode. Part `file` paths match what `unpack` writes.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::assets::{sniff_asset, AssetInfo};
+use crate::bundled::BundledEntry;
+use crate::extract::BlobLocation;
+use crate::graph::{Module, ModuleGraph, Offsets, StringPointer};
+use crate::kind::{Encoding, FileSide, Loader, ModuleFormat};
+use crate::runtime::{detect_runtime, RuntimeInfo};
+use crate::sources::SourceEntry;
+use crate::sourcemap::SerializedSourceMap;
+use crate::unpack::{bytecode_rel_path, module_rel_path, serialized_sourcemap_rel_path, sourcemap_rel_path};
+
+/// Bumped when the manifest schema changes incompatibly.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// File name of the machine-readable manifest inside an output directory.
+pub const MANIFEST_JSON: &str = "manifest.json";
+
+/// Container the blob was taken from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutableFormat {
+    Pe,
+    Elf,
+    #[serde(rename = "macho")]
+    MachO,
+    /// Raw .bun blob passed via `--blob`.
+    Blob,
+}
+
+impl fmt::Display for ExecutableFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pe => "pe",
+            Self::Elf => "elf",
+            Self::MachO => "macho",
+            Self::Blob => "blob",
+        })
+    }
+}
+
+/// Where the blob came from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceInfo {
+    pub path: Option<String>,
+    pub format: ExecutableFormat,
+    /// Mach-O architecture the blob was taken from.
+    #[serde(default)]
+    pub arch: Option<String>,
+    /// Bun runtime found in the executable outside the blob.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeInfo>,
+}
+
+impl SourceInfo {
+    /// Describes the executable `exe` read from `path` whose blob is at `loc`,
+    /// with the runtime version found outside the blob.
+    pub fn from_location(path: &Path, exe: &[u8], loc: &BlobLocation) -> Self {
+        Self {
+            runtime: detect_runtime(exe, loc.range.clone()),
+            ..Self::without_runtime(path, loc)
+        }
+    }
+
+    /// Like `from_location`, without searching the executable for the runtime.
+    pub fn without_runtime(path: &Path, loc: &BlobLocation) -> Self {
+        Self {
+            path: Some(path.display().to_string()),
+            format: loc.format,
+            arch: loc.arch.clone(),
+            runtime: None,
+        }
+    }
+}
+
+/// Top-level `manifest.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub manifest_version: u32,
+    pub source: SourceInfo,
+    pub blob_size: usize,
+    /// SHA-256 of the whole blob; `pack` compares against it to report a byte-exact round trip.
+    pub blob_sha256: String,
+    /// Name of the layout the blob was parsed with (see `layout.rs`); `pack`
+    /// writes the same one. Missing in older manifests, which means the current layout.
+    #[serde(default)]
+    pub layout: Option<String>,
+    pub offsets: Offsets,
+    /// Raw `compile_exec_argv` string (lossily decoded as UTF-8); `pack` writes it back.
+    pub compile_exec_argv: String,
+    /// Hex of the raw `compile_exec_argv` bytes when they are not valid UTF-8;
+    /// `pack` writes these instead of `compile_exec_argv`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compile_exec_argv_hex: Option<String>,
+    /// `compile_exec_argv` split into arguments (informational).
+    #[serde(default)]
+    pub exec_argv: Vec<String>,
+    /// Names of the set `offsets.flags` bits (informational).
+    #[serde(default)]
+    pub flags: Vec<String>,
+    pub entry_point_id: u32,
+    /// Raw name of the entry point module; `None` if `entry_point_id` is out of range.
+    pub entry_point: Option<String>,
+    pub modules: Vec<ModuleEntry>,
+    /// Original sources recovered from sourcemaps (`--sources`); empty otherwise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceEntry>,
+    /// Original files split from bundled modules (`--split-bundled`); empty otherwise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundled: Vec<BundledEntry>,
+}
+
+/// One module: identity, decoded flags and its non-empty parts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModuleEntry {
+    pub index: usize,
+    /// Raw virtual name (lossily decoded as UTF-8).
+    pub name: String,
+    /// Hex of the raw name bytes when they are not valid UTF-8; `pack` writes
+    /// these instead of `name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_hex: Option<String>,
+    /// Offset of `name` inside the blob.
+    pub name_offset: u32,
+    /// Sanitized relative path; empty if `name_to_rel_path` rejected the name.
+    pub rel_path: String,
+    pub encoding: Encoding,
+    pub loader: Loader,
+    pub module_format: ModuleFormat,
+    pub side: FileSide,
+    pub contents: Option<PartEntry>,
+    pub sourcemap: Option<PartEntry>,
+    /// JSON conversion of a sourcemap stored in Bun's serialized form (`sourcemap.file`
+    /// is then the raw `.bunmap`); `None` for JSON sourcemaps or if not written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sourcemap_json: Option<String>,
+    pub bytecode: Option<PartEntry>,
+    /// Detected type of `contents` (native addon, WASM, image, ...); `None` for
+    /// text and JavaScript.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<AssetInfo>,
+}
+
+/// One of contents / sourcemap / bytecode. Absent (`null`) when the pointer is
+/// (0, 0); a present pointer with length 0 keeps its offset so `pack` can
+/// reproduce the original layout.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PartEntry {
+    /// Path relative to the output directory; `None` if not written (empty part
+    /// or rejected module name).
+    pub file: Option<String>,
+    /// Offset of the part inside the blob.
+    pub offset: u32,
+    pub size: usize,
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// Builds the manifest for `graph`. File paths are those `write_module_graph` uses.
+    pub fn from_graph(graph: &ModuleGraph<'_>, source: SourceInfo) -> Self {
+        Self {
+            manifest_version: MANIFEST_VERSION,
+            source,
+            blob_size: graph.blob.len(),
+            blob_sha256: sha256_hex(graph.blob),
+            layout: Some(graph.layout.name.to_string()),
+            offsets: graph.offsets,
+            compile_exec_argv: String::from_utf8_lossy(graph.compile_exec_argv_bytes()).into_owned(),
+            compile_exec_argv_hex: non_utf8_hex(graph.compile_exec_argv_bytes()),
+            exec_argv: graph.compile_exec_argv(),
+            flags: graph.offsets.flag_names(),
+            entry_point_id: graph.entry_point_id,
+            entry_point: graph.entry_point().map(|m| m.name_str().into_owned()),
+            modules: graph
+                .modules
+                .iter()
+                .map(ModuleEntry::from_module)
+                .collect(),
+            sources: Vec::new(),
+            bundled: Vec::new(),
+        }
+    }
+
+    /// Raw `compile_exec_argv` bytes: from `compile_exec_argv_hex` if set.
+    pub fn compile_exec_argv_bytes(&self) -> Result<Vec<u8>, hex::FromHexError> {
+        raw_bytes(&self.compile_exec_argv, self.compile_exec_argv_hex.as_deref())
+    }
+}
+
+impl ModuleEntry {
+    /// Raw name bytes: from `name_hex` if set.
+    pub fn name_bytes(&self) -> Result<Vec<u8>, hex::FromHexError> {
+        raw_bytes(&self.name, self.name_hex.as_deref())
+    }
+
+    fn from_module(m: &Module<'_>) -> Self {
+        let rel_path = m.rel_path();
+        let file = module_rel_path(&rel_path);
+        let written = |file: String| (!rel_path.is_empty()).then_some(file);
+        let p = &m.pointers;
+        let serialized = SerializedSourceMap::parse(m.sourcemap).is_some();
+        let sourcemap_file = if serialized {
+            serialized_sourcemap_rel_path(&file)
+        } else {
+            sourcemap_rel_path(&file)
+        };
+        Self {
+            index: m.index,
+            name: m.name_str().into_owned(),
+            name_hex: non_utf8_hex(m.name),
+            name_offset: p.name.offset,
+            encoding: m.encoding,
+            loader: m.loader,
+            module_format: m.module_format,
+            side: m.side,
+            sourcemap: PartEntry::new(p.sourcemap, m.sourcemap, written(sourcemap_file.clone())),
+            sourcemap_json: serialized.then(|| written(sourcemap_rel_path(&file))).flatten(),
+            bytecode: PartEntry::new(p.bytecode, m.bytecode, written(bytecode_rel_path(&file))),
+            contents: PartEntry::new(p.contents, m.contents, written(file)),
+            asset: sniff_asset(m.contents),
+            rel_path,
+        }
+    }
+}
+
+impl PartEntry {
+    /// `None` for a (0, 0) pointer. `file` is dropped for an empty part since
+    /// `write_module_graph` skips it.
+    fn new(ptr: StringPointer, part: &[u8], file: Option<String>) -> Option<Self> {
+        if ptr == StringPointer::default() {
+            return None;
+        }
+        Some(Self {
+            file: file.filter(|_| !part.is_empty()),
+            offset: ptr.offset,
+            size: part.len(),
+            sha256: sha256_hex(part),
+        })
+    }
+}
+
+/// Hex of `bytes` if they are not valid UTF-8, i.e. if the lossy string would not round-trip.
+fn non_utf8_hex(bytes: &[u8]) -> Option<String> {
+    std::str::from_utf8(bytes).is_err().then(|| hex::encode(bytes))
+}
+
+fn raw_bytes(lossy: &str, hex: Option<&str>) -> Result<Vec<u8>, hex::FromHexError> {
+    match hex {
+        Some(hex) => hex::decode(hex),
+        None => Ok(lossy.as_bytes().to_vec()),
+    }
+}
+
+/// Lowercase hex SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
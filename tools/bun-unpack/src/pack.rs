@@ -0,0 +1,477 @@
+//! Rebuild a .bun blob from its parts: the inverse of `ModuleGraph::parse`.
+//!
+//! **Interaction**: `GraphBuilder::from_dir` reads an unpacked directory and its
+//! `manifest.json` (CLI `pack`); `GraphBuilder::from_graph` starts from a parsed
+//! graph when only Offsets-level data changes. `GraphBuilder::build` lays parts
+//! out in their original order and keeps the original gaps between them (NUL
+//! terminators, bytecode alignment padding) as zero bytes, so unchanged input
+//! reproduces the original blob byte for byte. The module table and Offsets are
+//! written in the layout the input was read with.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::graph::{ModuleGraph, Offsets, StringPointer, BUN_TRAILER};
+use crate::kind::{Encoding, FileSide, Loader, ModuleFormat};
+use crate::layout::Layout;
+use crate::manifest::{Manifest, PartEntry, MANIFEST_JSON};
+
+/// Bun aligns bytecode to this boundary; a grown part before it must not break that.
+pub const BYTECODE_ALIGN: usize = 128;
+
+/// One string region: where it was in the original blob and what to write now.
+#[derive(Clone, Debug, Default)]
+pub struct PackPart<'a> {
+    /// Pointer in the original blob; (0, 0) means the part was absent.
+    pub orig: StringPointer,
+    pub data: Cow<'a, [u8]>,
+}
+
+impl<'a> PackPart<'a> {
+    fn borrowed(orig: StringPointer, data: &'a [u8]) -> Self {
+        Self {
+            orig,
+            data: Cow::Borrowed(data),
+        }
+    }
+}
+
+/// One module table entry to be written.
+#[derive(Clone, Debug)]
+pub struct PackModule<'a> {
+    pub name: PackPart<'a>,
+    pub contents: PackPart<'a>,
+    pub sourcemap: PackPart<'a>,
+    pub bytecode: PackPart<'a>,
+    pub encoding: Encoding,
+    pub loader: Loader,
+    pub module_format: ModuleFormat,
+    pub side: FileSide,
+}
+
+/// Everything needed to lay out a blob: modules, exec argv and the original
+/// positions of the module table and end of data.
+#[derive(Clone, Debug)]
+pub struct GraphBuilder<'a> {
+    /// Layout to write the module table and Offsets in.
+    pub layout: &'static Layout,
+    pub modules: Vec<PackModule<'a>>,
+    pub compile_exec_argv: PackPart<'a>,
+    /// Original module table pointer; the table keeps its place in the layout.
+    pub modules_ptr: StringPointer,
+    /// Original `Offsets.byte_count`; any gap between the last part and Offsets is kept.
+    pub byte_count: u64,
+    pub entry_point_id: u32,
+    pub flags: u32,
+}
+
+impl<'a> GraphBuilder<'a> {
+    /// Builder that reproduces `graph` exactly; fields can be edited before `build`.
+    pub fn from_graph(graph: &ModuleGraph<'a>) -> Self {
+        let off = &graph.offsets;
+        Self {
+            layout: graph.layout,
+            modules: graph
+                .modules
+                .iter()
+                .map(|m| PackModule {
+                    name: PackPart::borrowed(m.pointers.name, m.name),
+                    contents: PackPart::borrowed(m.pointers.contents, m.contents),
+                    sourcemap: PackPart::borrowed(m.pointers.sourcemap, m.sourcemap),
+                    bytecode: PackPart::borrowed(m.pointers.bytecode, m.bytecode),
+                    encoding: m.encoding,
+                    loader: m.loader,
+                    module_format: m.module_format,
+                    side: m.side,
+                })
+                .collect(),
+            compile_exec_argv: PackPart::borrowed(
+                StringPointer {
+                    offset: off.compile_exec_argv_offset,
+                    length: off.compile_exec_argv_length,
+                },
+                graph.compile_exec_argv_bytes(),
+            ),
+            modules_ptr: StringPointer {
+                offset: off.modules_ptr_offset,
+                length: off.modules_ptr_length,
+            },
+            byte_count: off.byte_count,
+            entry_point_id: graph.entry_point_id,
+            flags: off.flags,
+        }
+    }
+
+    /// Replaces the baked exec argv (`--compile-exec-argv`); empty clears it.
+    /// Module parts are untouched, though later parts may move if it grows.
+    pub fn set_compile_exec_argv(&mut self, argv: &str) {
+        self.compile_exec_argv.data = Cow::Owned(argv.as_bytes().to_vec());
+    }
+
+    /// Lays out all parts and returns the blob (data, module table, Offsets, trailer).
+    ///
+    /// Parts are placed in order of their original offset with the original gap
+    /// before each one. Parts that were absent but now have data are appended
+    /// after the original ones, each followed by a NUL like Bun's `appendCountZ`.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        self.check_layout()?;
+        let layout = self.layout;
+        let table = PackPart {
+            orig: self.modules_ptr,
+            data: Cow::Owned(vec![0u8; self.modules.len() * layout.module_size]),
+        };
+        // Region order: 4 per module (name, contents, sourcemap, bytecode), then argv, then table.
+        let mut regions: Vec<(&PackPart<'_>, usize)> = Vec::with_capacity(self.modules.len() * 4 + 2);
+        for m in &self.modules {
+            regions.push((&m.name, 1));
+            regions.push((&m.contents, 1));
+            regions.push((&m.sourcemap, 1));
+            regions.push((&m.bytecode, BYTECODE_ALIGN));
+        }
+        let argv_idx = regions.len();
+        regions.push((&self.compile_exec_argv, 1));
+        let table_idx = regions.len();
+        regions.push((&table, 1));
+
+        let is_new = |p: &PackPart<'_>| p.orig == StringPointer::default();
+        let mut order: Vec<usize> = (0..regions.len())
+            .filter(|&i| !(is_new(regions[i].0) && regions[i].0.data.is_empty()))
+            .collect();
+        order.sort_by_key(|&i| {
+            let p = regions[i].0;
+            (is_new(p), p.orig.offset, p.orig.length)
+        });
+
+        let mut out = Vec::new();
+        let mut new_ptrs = vec![StringPointer::default(); regions.len()];
+        let mut placed: HashMap<StringPointer, usize> = HashMap::new();
+        let mut orig_end = 0u64;
+        for i in order {
+            let (part, align) = regions[i];
+            if is_new(part) {
+                new_ptrs[i] = place(&mut out, 0, 1, &part.data)?;
+                out.push(0);
+                continue;
+            }
+            if let Some(&first) = placed.get(&part.orig) {
+                if regions[first].0.data != part.data {
+                    anyhow::bail!(
+                        "parts sharing blob range {}+{} now differ; cannot pack",
+                        part.orig.offset,
+                        part.orig.length
+                    );
+                }
+                new_ptrs[i] = new_ptrs[first];
+                continue;
+            }
+            if (part.orig.offset as u64) < orig_end {
+                anyhow::bail!(
+                    "part at {}+{} overlaps the previous one (ends at {}); cannot pack",
+                    part.orig.offset,
+                    part.orig.length,
+                    orig_end
+                );
+            }
+            let gap = (part.orig.offset as u64 - orig_end) as usize;
+            let align = if (part.orig.offset as usize).is_multiple_of(align) { align } else { 1 };
+            new_ptrs[i] = place(&mut out, gap, align, &part.data)?;
+            placed.insert(part.orig, i);
+            orig_end = part.orig.end();
+        }
+        out.resize(out.len() + self.byte_count.saturating_sub(orig_end) as usize, 0);
+
+        let table_ptr = new_ptrs[table_idx];
+        let table_out = &mut out[table_ptr.offset as usize..][..table_ptr.length as usize];
+        let entries = table_out.chunks_mut(layout.module_size);
+        for ((m, ptrs), entry) in self.modules.iter().zip(new_ptrs.chunks(4)).zip(entries) {
+            let kinds = [m.encoding.as_u8(), m.loader.as_u8(), m.module_format.as_u8(), m.side.as_u8()];
+            layout.write_entry(entry, ptrs, kinds);
+        }
+
+        let argv_ptr = new_ptrs[argv_idx];
+        let offsets = Offsets {
+            byte_count: out.len() as u64,
+            modules_ptr_offset: table_ptr.offset,
+            modules_ptr_length: table_ptr.length,
+            entry_point_id: self.entry_point_id,
+            compile_exec_argv_offset: argv_ptr.offset,
+            compile_exec_argv_length: argv_ptr.length,
+            flags: self.flags,
+        };
+        out.extend_from_slice(&layout.encode_offsets(&offsets));
+        out.extend_from_slice(BUN_TRAILER);
+        Ok(out)
+    }
+
+    /// Fails if a field has a value `self.layout` has no room for.
+    fn check_layout(&self) -> Result<()> {
+        let layout = self.layout;
+        let unsupported = |what: &str| anyhow::anyhow!("layout {} has no {}; cannot pack", layout.name, what);
+        if !layout.has_exec_argv && (!self.compile_exec_argv.data.is_empty() || self.flags != 0) {
+            return Err(unsupported("compile_exec_argv or flags"));
+        }
+        for m in &self.modules {
+            if !layout.has_bytecode && !m.bytecode.data.is_empty() {
+                return Err(unsupported("bytecode"));
+            }
+            if !layout.has_module_format && m.module_format != ModuleFormat::None {
+                return Err(unsupported("module_format"));
+            }
+            if !layout.has_side && m.side != FileSide::Server {
+                return Err(unsupported("side"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GraphBuilder<'static> {
+    /// Reads `dir/manifest.json` and every written part file under `dir`.
+    /// Returns the manifest alongside the builder.
+    pub fn from_dir(dir: &Path) -> Result<(Manifest, Self)> {
+        let manifest_path = dir.join(MANIFEST_JSON);
+        let json = fs::read(&manifest_path).with_context(|| format!("read {:?}", manifest_path))?;
+        let manifest: Manifest =
+            serde_json::from_slice(&json).with_context(|| format!("parse {:?}", manifest_path))?;
+
+        let read_part = |part: &Option<PartEntry>, what: &str, index: usize| -> Result<PackPart<'static>> {
+            let Some(p) = part else {
+                return Ok(PackPart::default());
+            };
+            let orig = StringPointer {
+                offset: p.offset,
+                length: p.size as u32,
+            };
+            if p.size == 0 {
+                return Ok(PackPart { orig, data: Cow::Owned(Vec::new()) });
+            }
+            let file = p
+                .file
+                .as_deref()
+                .with_context(|| format!("module [{}] {} was not written (rejected name or filtered out); cannot pack", index, what))?;
+            let path = dir.join(file);
+            let data = fs::read(&path).with_context(|| format!("read {} {:?}", what, path))?;
+            Ok(PackPart { orig, data: Cow::Owned(data) })
+        };
+
+        let mut modules = Vec::with_capacity(manifest.modules.len());
+        for m in &manifest.modules {
+            let name = m
+                .name_bytes()
+                .with_context(|| format!("module [{}] has an invalid name_hex in {:?}", m.index, manifest_path))?;
+            modules.push(PackModule {
+                name: PackPart {
+                    orig: StringPointer {
+                        offset: m.name_offset,
+                        length: name.len() as u32,
+                    },
+                    data: Cow::Owned(name),
+                },
+                contents: read_part(&m.contents, "contents", m.index)?,
+                sourcemap: read_part(&m.sourcemap, "sourcemap", m.index)?,
+                bytecode: read_part(&m.bytecode, "bytecode", m.index)?,
+                encoding: m.encoding,
+                loader: m.loader,
+                module_format: m.module_format,
+                side: m.side,
+            });
+        }
+
+        let layout = match manifest.layout.as_deref() {
+            Some(name) => Layout::by_name(name).with_context(|| format!("unknown layout {:?} in {:?}", name, manifest_path))?,
+            None => Layout::current(),
+        };
+        let compile_exec_argv = manifest
+            .compile_exec_argv_bytes()
+            .with_context(|| format!("invalid compile_exec_argv_hex in {:?}", manifest_path))?;
+        let off = &manifest.offsets;
+        let builder = Self {
+            layout,
+            modules,
+            compile_exec_argv: PackPart {
+                orig: StringPointer {
+                    offset: off.compile_exec_argv_offset,
+                    length: off.compile_exec_argv_length,
+                },
+                data: Cow::Owned(compile_exec_argv),
+            },
+            modules_ptr: StringPointer {
+                offset: off.modules_ptr_offset,
+                length: off.modules_ptr_length,
+            },
+            byte_count: off.byte_count,
+            entry_point_id: manifest.entry_point_id,
+            flags: off.flags,
+        };
+        Ok((manifest, builder))
+    }
+}
+
+/// Appends `gap` zero bytes, pads to `align`, then appends `data`. Returns its pointer.
+fn place(out: &mut Vec<u8>, gap: usize, align: usize, data: &[u8]) -> Result<StringPointer> {
+    let mut offset = out.len() + gap;
+    if !data.is_empty() {
+        offset = offset.next_multiple_of(align);
+    }
+    out.resize(offset, 0);
+    out.extend_from_slice(data);
+    let too_big = || anyhow::anyhow!("packed blob exceeds u32 offsets (at {}, {} bytes)", offset, data.len());
+    Ok(StringPointer {
+        offset: u32::try_from(offset).map_err(|_| too_big())?,
+        length: u32::try_from(data.len()).map_err(|_| too_big())?,
+    })
+}
+
+/// Packs an unpacked directory back into a blob. Returns the manifest it was
+/// built from and the blob.
+pub fn pack_dir(dir: &Path) -> Result<(Manifest, Vec<u8>)> {
+    let (manifest, builder) = GraphBuilder::from_dir(dir)?;
+    let blob = builder.build()?;
+    Ok((manifest, blob))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::{sample_blob, BYTECODE};
+    use crate::layout::LAYOUTS;
+    use crate::manifest::SourceInfo;
+    use crate::unpack::{module_rel_path, write_module_graph, write_modules, UnpackOptions};
+
+    /// Fresh empty directory under the system temp dir.
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("bun-unpack-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn blob_source() -> SourceInfo {
+        SourceInfo {
+            path: None,
+            format: crate::manifest::ExecutableFormat::Blob,
+            arch: None,
+            runtime: None,
+        }
+    }
+
+    #[test]
+    fn from_graph_round_trips_every_layout() {
+        for layout in LAYOUTS {
+            let blob = sample_blob(layout);
+            let graph = ModuleGraph::parse(&blob).unwrap();
+            assert_eq!(GraphBuilder::from_graph(&graph).build().unwrap(), blob, "layout {}", layout.name);
+        }
+    }
+
+    #[test]
+    fn pack_dir_round_trips_every_layout() {
+        for layout in LAYOUTS {
+            let blob = sample_blob(layout);
+            let graph = ModuleGraph::parse(&blob).unwrap();
+            let dir = temp_dir(&format!("pack-{}", layout.name));
+            write_module_graph(&graph, &dir, blob_source()).unwrap();
+            let (manifest, packed) = pack_dir(&dir).unwrap();
+            assert_eq!(manifest.layout.as_deref(), Some(layout.name));
+            assert_eq!(packed, blob, "layout {}", layout.name);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn pack_dir_keeps_non_utf8_names() {
+        let blob = sample_blob(Layout::current());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.modules[1].name.data = Cow::Borrowed(b"B:/~BUN/root/lib/ut\xefl.js");
+        let blob = builder.build().unwrap();
+        let graph = ModuleGraph::parse(&blob).unwrap();
+
+        let dir = temp_dir("pack-non-utf8");
+        write_module_graph(&graph, &dir, blob_source()).unwrap();
+        let (manifest, packed) = pack_dir(&dir).unwrap();
+        assert!(manifest.modules[1].name_hex.is_some());
+        assert_eq!(packed, blob);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn module_paths_do_not_overwrite_generated_files() {
+        assert_eq!(module_rel_path("manifest.json"), "manifest~1.json");
+        assert_eq!(module_rel_path("manifest.txt/a.js"), "manifest~1.txt/a.js");
+        assert_eq!(module_rel_path("sources/a.ts"), "sources~1/a.ts");
+        assert_eq!(module_rel_path("bundled"), "bundled~1");
+        assert_eq!(module_rel_path("root/sources/a.ts"), "root/sources/a.ts");
+        assert_eq!(module_rel_path("manifest.json.js"), "manifest.json.js");
+
+        let blob = sample_blob(Layout::current());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.modules[0].name.data = Cow::Borrowed(b"B:/~BUN/manifest.json");
+        builder.modules[1].name.data = Cow::Borrowed(b"B:/~BUN/sources/util.js");
+        let blob = builder.build().unwrap();
+        let graph = ModuleGraph::parse(&blob).unwrap();
+
+        let dir = temp_dir("pack-generated-names");
+        let options = UnpackOptions {
+            sources: true,
+            bundled: true,
+            assets_only: false,
+        };
+        let manifest = write_modules(&graph, &dir, blob_source(), &options, |_| true).unwrap();
+        let files = |i: usize| {
+            let m = &manifest.modules[i];
+            [&m.contents, &m.sourcemap, &m.bytecode].map(|p| p.as_ref().and_then(|p| p.file.as_deref()))
+        };
+        assert_eq!(files(0), [Some("manifest~1.json"), Some("manifest~1.json.map"), Some("manifest~1.json.jsc")]);
+        assert_eq!(files(1), [Some("sources~1/util.js"), None, None]);
+        assert_eq!(manifest.modules[0].rel_path, "manifest.json");
+        assert_eq!(fs::read(dir.join("manifest~1.json")).unwrap(), graph.modules[0].contents);
+        let written: Manifest = serde_json::from_slice(&fs::read(dir.join(MANIFEST_JSON)).unwrap()).unwrap();
+        assert_eq!(written.modules.len(), 2);
+        let (_, packed) = pack_dir(&dir).unwrap();
+        assert_eq!(packed, blob);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn grown_part_keeps_bytecode_aligned() {
+        let blob = sample_blob(Layout::current());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.modules[0].contents.data = Cow::Owned(vec![b'x'; 300]);
+        let packed = builder.build().unwrap();
+        let graph = ModuleGraph::parse(&packed).unwrap();
+        let cli = &graph.modules[0];
+        assert_eq!(cli.contents, vec![b'x'; 300]);
+        assert_eq!(cli.bytecode, BYTECODE);
+        assert_eq!(cli.pointers.bytecode.offset as usize % BYTECODE_ALIGN, 0);
+        assert_eq!(graph.modules[1].contents, b"export const x = 1;\n");
+    }
+
+    #[test]
+    fn set_compile_exec_argv() {
+        let blob = sample_blob(Layout::current());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.set_compile_exec_argv("--smol --inspect");
+        let packed = builder.build().unwrap();
+        let graph = ModuleGraph::parse(&packed).unwrap();
+        assert_eq!(graph.compile_exec_argv(), ["--smol", "--inspect"]);
+        assert_eq!(graph.modules[0].sourcemap, crate::graph::tests::SOURCEMAP);
+    }
+
+    #[test]
+    fn old_layout_rejects_fields_it_cannot_hold() {
+        let blob = sample_blob(Layout::by_name("v1").unwrap());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.set_compile_exec_argv("--smol");
+        let err = builder.build().unwrap_err().to_string();
+        assert!(err.contains("layout v1 has no compile_exec_argv"), "{}", err);
+    }
+}
@@ -0,0 +1,294 @@
+//! Detect the version and git revision of the Bun runtime an executable was
+//! compiled with.
+//!
+//! **Interaction**: `SourceInfo::from_location` records the result in
+//! `manifest.json` as `source.runtime` (the CLI searches only for commands
+//! that report the runtime or write the graph back); `parse_for_runtime` passes its
+//! `layout_hint` to `ModuleGraph::parse_with_hint`, so the layout written by
+//! that Bun version is tried first. Only bytes outside
+//! the blob are searched: embedded JavaScript often carries other `Bun/x.y.z`
+//! strings. The runtime embeds its version in its user agent (`Bun/1.2.3`) and
+//! as `1.2.3+<short revision>` (`bun --revision`); the full 40-character
+//! revision (`process.revision`) is looked up from the short one.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use memchr::memmem;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ParseError;
+use crate::graph::ModuleGraph;
+use crate::layout::{Layout, LAYOUTS};
+
+/// Newest Bun release line (major, minor) the layouts were checked against; a
+/// runtime from a later line may write a layout this tool does not know.
+pub const NEWEST_KNOWN_BUN: (u32, u32) = (1, 2);
+
+/// Prefixes that precede the runtime's version string.
+const VERSION_PREFIXES: [&[u8]; 2] = [b"Bun/", b"Bun v"];
+
+/// A Bun release number; pre-release suffixes (`-canary.N`) are ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BunVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl BunVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Parses `1.2.3` or `1.2.3-canary.1`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let core = s.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u32>().ok());
+        let version = Self::new(parts.next()??, parts.next()??, parts.next()??);
+        parts.next().is_none().then_some(version)
+    }
+
+    /// Layout this version writes: the newest one introduced at or before it.
+    pub fn layout(self) -> Option<&'static Layout> {
+        LAYOUTS.iter().find(|l| l.since <= self)
+    }
+
+    /// Released after every line the layouts were checked against.
+    pub fn is_newer_than_known(self) -> bool {
+        (self.major, self.minor) > NEWEST_KNOWN_BUN
+    }
+}
+
+impl fmt::Display for BunVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Bun runtime found in an executable.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeInfo {
+    /// Version as embedded, e.g. `1.2.16` or `1.2.17-canary.20250601.1`.
+    pub version: String,
+    /// Git revision of the Bun build: full if found, otherwise the short form.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+impl RuntimeInfo {
+    pub fn bun_version(&self) -> Option<BunVersion> {
+        BunVersion::parse(&self.version)
+    }
+
+    /// Layout the runtime's version is known to write.
+    pub fn layout_hint(&self) -> Option<&'static Layout> {
+        self.bun_version()?.layout()
+    }
+}
+
+impl fmt::Display for RuntimeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bun {}", self.version)?;
+        if let Some(revision) = &self.revision {
+            write!(f, " ({})", revision)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `blob` trying the layout of `runtime` first. Also returns warnings
+/// for a runtime newer than the known layouts and for a blob whose layout is
+/// not the one its runtime version writes.
+pub fn parse_for_runtime<'a>(
+    blob: &'a [u8],
+    runtime: Option<&RuntimeInfo>,
+) -> Result<(ModuleGraph<'a>, Vec<String>), ParseError> {
+    let mut warnings = Vec::new();
+    if let Some(version) = runtime.and_then(RuntimeInfo::bun_version) {
+        if version.is_newer_than_known() {
+            warnings.push(format!(
+                "Bun {} is newer than the layouts this tool knows (checked up to {}.{}.x); if parsing fails, try --layout",
+                version, NEWEST_KNOWN_BUN.0, NEWEST_KNOWN_BUN.1
+            ));
+        }
+    }
+    let hint = runtime.and_then(RuntimeInfo::layout_hint);
+    let graph = ModuleGraph::parse_with_hint(blob, hint)?;
+    if let (Some(runtime), Some(hint)) = (runtime, hint) {
+        if graph.layout.name != hint.name {
+            warnings.push(format!(
+                "{} writes layout {}, but the blob parsed as {}",
+                runtime, hint.name, graph.layout.name
+            ));
+        }
+    }
+    Ok((graph, warnings))
+}
+
+/// Searches `exe` outside `blob` for the runtime's version and revision. The
+/// version seen most often after a known prefix wins; `None` if there is none.
+pub fn detect_runtime(exe: &[u8], blob: Range<usize>) -> Option<RuntimeInfo> {
+    let regions = [&exe[..blob.start.min(exe.len())], &exe[blob.end.min(exe.len())..]];
+
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for region in regions {
+        for prefix in VERSION_PREFIXES {
+            for at in memmem::find_iter(region, prefix) {
+                if let Some(version) = version_at(&region[at + prefix.len()..]) {
+                    *counts.entry(version).or_default() += 1;
+                }
+            }
+        }
+    }
+    let version = counts
+        .into_iter()
+        .max_by_key(|&(v, n)| (n, BunVersion::parse(std::str::from_utf8(v).unwrap_or_default())))?
+        .0;
+
+    // `<version>+<short revision>`, then the full revision starting with it.
+    let mut with_plus = version.to_vec();
+    with_plus.push(b'+');
+    let short = regions.iter().find_map(|region| {
+        memmem::find_iter(region, &with_plus)
+            .find_map(|at| hex_run(&region[at + with_plus.len()..]).filter(|h| h.len() >= 7))
+    });
+    let revision = short.map(|short| {
+        let full = regions.iter().find_map(|region| {
+            memmem::find_iter(region, short).find_map(|at| {
+                let before_is_hex = at > 0 && region[at - 1].is_ascii_hexdigit();
+                hex_run(&region[at..]).filter(|h| h.len() == 40 && !before_is_hex)
+            })
+        });
+        String::from_utf8_lossy(full.unwrap_or(short)).into_owned()
+    });
+
+    Some(RuntimeInfo {
+        version: String::from_utf8_lossy(version).into_owned(),
+        revision,
+    })
+}
+
+/// `1.2.3` with an optional `-canary.20250101.1` style suffix at the start of `bytes`.
+fn version_at(bytes: &[u8]) -> Option<&[u8]> {
+    let core = bytes
+        .iter()
+        .position(|&b| !(b.is_ascii_digit() || b == b'.'))
+        .unwrap_or(bytes.len());
+    let mut end = core;
+    if bytes.get(core) == Some(&b'-') {
+        end = core
+            + 1
+            + bytes[core + 1..]
+                .iter()
+                .position(|&b| !(b.is_ascii_alphanumeric() || b == b'.'))
+                .unwrap_or(bytes.len() - core - 1);
+        if end == core + 1 {
+            end = core;
+        }
+    }
+    let version = bytes[..end].strip_suffix(b".").unwrap_or(&bytes[..end]);
+    BunVersion::parse(std::str::from_utf8(version).ok()?).map(|_| version)
+}
+
+/// Lowercase hex digits at the start of `bytes`.
+fn hex_run(bytes: &[u8]) -> Option<&[u8]> {
+    let len = bytes
+        .iter()
+        .position(|&b| !matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        .unwrap_or(bytes.len());
+    (len > 0).then(|| &bytes[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+
+    const REVISION: &str = "0d9b296af0f40e1d2a7c5b2e0cfe3f0a1b2c3d4e";
+
+    /// `before`, a fake blob holding `inside`, then `after`; returns the blob range too.
+    fn with_blob(before: &str, inside: &str, after: &str) -> (Vec<u8>, Range<usize>) {
+        let blob = before.len()..before.len() + inside.len();
+        (format!("{}{}{}", before, inside, after).into_bytes(), blob)
+    }
+
+    fn runtime(version: &str) -> RuntimeInfo {
+        RuntimeInfo {
+            version: version.to_string(),
+            revision: None,
+        }
+    }
+
+    #[test]
+    fn version_and_suffixes() {
+        assert_eq!(version_at(b"1.2.16 (Linux x64)"), Some(&b"1.2.16"[..]));
+        assert_eq!(version_at(b"1.2.17-canary.20250601.1\0"), Some(&b"1.2.17-canary.20250601.1"[..]));
+        // A sentence-ending dot and a bare `-` are not part of it.
+        assert_eq!(version_at(b"1.1.30."), Some(&b"1.1.30"[..]));
+        assert_eq!(version_at(b"1.2.3-"), Some(&b"1.2.3"[..]));
+        assert_eq!(version_at(b"1.2"), None);
+        assert_eq!(version_at(b"x1.2.3"), None);
+        assert_eq!(BunVersion::parse("1.2.3+0d9b296af"), Some(BunVersion::new(1, 2, 3)));
+        assert_eq!(BunVersion::parse("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn ignores_strings_inside_the_blob() {
+        let (exe, blob) = with_blob("ua Bun/1.1.30\0", "fetch('x', {ua: 'Bun/1.2.16'}) Bun/1.2.16 Bun/1.2.16", "");
+        assert_eq!(detect_runtime(&exe, blob.clone()).unwrap().version, "1.1.30");
+        assert_eq!(detect_runtime(&exe[blob.clone()], 0..0).unwrap().version, "1.2.16");
+        assert_eq!(detect_runtime(&exe, 0..exe.len()), None);
+    }
+
+    #[test]
+    fn finds_the_revision_next_to_the_version() {
+        let short = &REVISION[..9];
+        let before = format!("Bun v1.2.16 \0 1.2.16+{}\0 {}\0", short, REVISION);
+        let (exe, blob) = with_blob(&before, "blob", "");
+        let info = detect_runtime(&exe, blob).unwrap();
+        assert_eq!(info.revision.as_deref(), Some(REVISION));
+        assert_eq!(info.to_string(), format!("Bun 1.2.16 ({})", REVISION));
+
+        // Without the full revision the short one is kept; a longer hex run
+        // that merely contains it is not the revision.
+        let before = format!("Bun/1.2.16\0 1.2.16+{}\0 ff{}\0", short, REVISION);
+        let (exe, blob) = with_blob(&before, "", "");
+        assert_eq!(detect_runtime(&exe, blob).unwrap().revision.as_deref(), Some(short));
+
+        // A version without `+revision` has none.
+        let (exe, blob) = with_blob("Bun/1.2.16 1.2.16+xyz", "", "");
+        assert_eq!(detect_runtime(&exe, blob).unwrap().revision, None);
+    }
+
+    #[test]
+    fn most_frequent_version_wins() {
+        let (exe, blob) = with_blob("Bun/1.1.30 ", "", " Bun/1.2.16 Bun v1.2.16");
+        assert_eq!(detect_runtime(&exe, blob).unwrap().version, "1.2.16");
+        // On a tie, the newer version wins.
+        let (exe, blob) = with_blob("Bun/1.2.16 Bun/1.1.30", "", "");
+        assert_eq!(detect_runtime(&exe, blob).unwrap().version, "1.2.16");
+    }
+
+    #[test]
+    fn warns_for_newer_runtime_and_layout_mismatch() {
+        let v3 = sample_blob(Layout::by_name("v3").unwrap());
+        let (graph, warnings) = parse_for_runtime(&v3, Some(&runtime("1.2.16"))).unwrap();
+        assert_eq!((graph.layout.name, warnings.len()), ("v3", 0));
+
+        let (graph, warnings) = parse_for_runtime(&v3, Some(&runtime("1.3.1"))).unwrap();
+        assert_eq!(graph.layout.name, "v3");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Bun 1.3.1 is newer than the layouts this tool knows"), "{}", warnings[0]);
+
+        let v2 = sample_blob(Layout::by_name("v2").unwrap());
+        let (graph, warnings) = parse_for_runtime(&v2, Some(&runtime("1.2.16"))).unwrap();
+        assert_eq!(graph.layout.name, "v2");
+        assert_eq!(warnings, ["Bun 1.2.16 writes layout v3, but the blob parsed as v2"]);
+
+        // No runtime, or an unparseable version: no hint and no warnings.
+        assert!(parse_for_runtime(&v2, None).unwrap().1.is_empty());
+        assert!(parse_for_runtime(&v2, Some(&runtime("canary"))).unwrap().1.is_empty());
+    }
+}
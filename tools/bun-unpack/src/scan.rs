@@ -0,0 +1,241 @@
+//! Carve module graphs out of arbitrary bytes (crash dumps, installer payloads,
+//! truncated downloads) by searching for the Bun trailer.
+//!
+//! **Interaction**: Used by the CLI `scan` subcommand. For every trailer hit,
+//! each known layout's `Offsets.byte_count` is followed backwards to a candidate
+//! blob, which must pass `ModuleGraph::parse_with_layout` plus
+//! `check_invariants`. Hits that fail are kept with the reason, and valid graphs
+//! that overlap an earlier one are flagged, so nothing is skipped silently.
+
+use std::fmt;
+use std::ops::Range;
+
+use memchr::memmem;
+use serde::{Serialize, Serializer};
+
+use crate::error::{LayoutAttempt, ParseError};
+use crate::graph::{read_u64_le, ModuleGraph, BUN_TRAILER};
+use crate::layout::{Layout, LAYOUTS};
+
+/// One trailer found in the scanned bytes.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScanHit {
+    /// File offset of the trailer.
+    pub trailer_offset: usize,
+    /// Blob range in the file; `None` if no layout produced a valid graph.
+    pub range: Option<Range<usize>>,
+    /// Name of the layout the graph parsed with.
+    pub layout: Option<&'static str>,
+    pub modules: usize,
+    /// Raw name of the entry point module.
+    pub entry_point: Option<String>,
+    pub status: ScanStatus,
+}
+
+impl ScanHit {
+    /// The graph this hit describes, re-parsed from `buf`; `None` if invalid.
+    pub fn graph<'a>(&self, buf: &'a [u8]) -> Option<ModuleGraph<'a>> {
+        let layout = Layout::by_name(self.layout?)?;
+        ModuleGraph::parse_with_layout(&buf[self.range.clone()?], layout).ok()
+    }
+}
+
+/// Outcome of validating one trailer hit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScanStatus {
+    /// A complete graph that shares no bytes with an earlier hit.
+    Ok,
+    /// A complete graph overlapping the valid graph of hit `with` (e.g. nested in it).
+    Overlaps { with: usize },
+    /// No layout produced a valid graph: the data is partial (truncated before
+    /// the trailer) or the bytes merely contain the trailer string.
+    Invalid(ParseError),
+}
+
+impl fmt::Display for ScanStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => f.write_str("ok"),
+            Self::Overlaps { with } => write!(f, "overlaps #{}", with),
+            Self::Invalid(e) => write!(f, "invalid: {}", e),
+        }
+    }
+}
+
+impl Serialize for ScanStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Finds every trailer in `buf` and validates the graph ending at each, in file order.
+pub fn scan_graphs(buf: &[u8]) -> Vec<ScanHit> {
+    let mut hits: Vec<ScanHit> = Vec::new();
+    for pos in memmem::find_iter(buf, BUN_TRAILER) {
+        let mut hit = match_graph(buf, pos);
+        if let Some(range) = &hit.range {
+            let earlier = hits.iter().position(|h| {
+                h.status == ScanStatus::Ok
+                    && h.range.as_ref().is_some_and(|r| r.start < range.end && range.start < r.end)
+            });
+            if let Some(with) = earlier {
+                hit.status = ScanStatus::Overlaps { with };
+            }
+        }
+        hits.push(hit);
+    }
+    hits
+}
+
+/// Tries each layout for the trailer at `pos`; the first valid graph wins.
+fn match_graph(buf: &[u8], pos: usize) -> ScanHit {
+    let end = pos + BUN_TRAILER.len();
+    let mut tried = Vec::with_capacity(LAYOUTS.len());
+    for layout in LAYOUTS {
+        // Start of the candidate blob; a byte_count reaching past the start of
+        // the file leaves the whole prefix, so parse reports it out of range.
+        let start = pos
+            .checked_sub(layout.offsets_size)
+            .and_then(|offsets_start| offsets_start.checked_sub(read_u64_le(buf, offsets_start) as usize))
+            .unwrap_or(0);
+        let parsed = ModuleGraph::parse_with_layout(&buf[start..end], layout)
+            .and_then(|g| g.check_invariants().map(|()| g));
+        match parsed {
+            Ok(graph) => {
+                return ScanHit {
+                    trailer_offset: pos,
+                    range: Some(start..end),
+                    layout: Some(layout.name),
+                    modules: graph.modules.len(),
+                    entry_point: graph.entry_point().map(|m| m.name_str().into_owned()),
+                    status: ScanStatus::Ok,
+                }
+            }
+            Err(error) => tried.push(LayoutAttempt { layout: layout.name, error }),
+        }
+    }
+    ScanHit {
+        trailer_offset: pos,
+        range: None,
+        layout: None,
+        modules: 0,
+        entry_point: None,
+        status: ScanStatus::Invalid(ParseError::NoMatchingLayout { tried }),
+    }
+}
+
+/// Formats `hits` as a header plus one row per hit.
+pub fn format_scan_table(hits: &[ScanHit]) -> Vec<String> {
+    let mut lines = Vec::with_capacity(hits.len() + 2);
+    lines.push(format!(
+        "{:>4}  {:>12} {:>12} {:>10}  {:<6} {:>7}  {}",
+        "#", "start", "trailer", "size", "layout", "modules", "status"
+    ));
+    for (i, h) in hits.iter().enumerate() {
+        let (start, size) = match &h.range {
+            Some(r) => (format!("{:#x}", r.start), r.len().to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let mut status = h.status.to_string();
+        if let Some(entry) = &h.entry_point {
+            status.push_str(&format!(" (entry {})", entry));
+        }
+        lines.push(format!(
+            "{:>4}  {:>12} {:>12} {:>10}  {:<6} {:>7}  {}",
+            i,
+            start,
+            format!("{:#x}", h.trailer_offset),
+            size,
+            h.layout.unwrap_or("-"),
+            h.modules,
+            status
+        ));
+    }
+    let valid = hits.iter().filter(|h| h.range.is_some()).count();
+    lines.push(format!("{} trailers, {} graphs", hits.len(), valid));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::graph::tests::{sample_blob, with_offsets};
+    use crate::pack::GraphBuilder;
+
+    fn v3() -> &'static Layout {
+        Layout::by_name("v3").unwrap()
+    }
+
+    /// `parts` joined, with the start offset of each.
+    fn join(parts: &[&[u8]]) -> (Vec<u8>, Vec<usize>) {
+        let mut buf = Vec::new();
+        let mut starts = Vec::new();
+        for part in parts {
+            starts.push(buf.len());
+            buf.extend_from_slice(part);
+        }
+        (buf, starts)
+    }
+
+    #[test]
+    fn finds_graphs_between_junk() {
+        let blob = sample_blob(v3());
+        let (buf, starts) = join(&[b"\x7fELF junk", &blob, &[0xcc; 100], &blob, b"tail"]);
+        let hits = scan_graphs(&buf);
+        assert_eq!(hits.len(), 2);
+        for (hit, start) in hits.iter().zip([starts[1], starts[3]]) {
+            assert_eq!(hit.status, ScanStatus::Ok);
+            assert_eq!(hit.range, Some(start..start + blob.len()));
+            assert_eq!(hit.trailer_offset, start + blob.len() - BUN_TRAILER.len());
+            assert_eq!((hit.layout, hit.modules), (Some("v3"), 2));
+            assert_eq!(hit.entry_point.as_deref(), Some("B:/~BUN/root/cli.js"));
+            assert_eq!(hit.graph(&buf).unwrap().modules.len(), 2);
+        }
+        assert_eq!(format_scan_table(&hits).last().unwrap(), "2 trailers, 2 graphs");
+    }
+
+    #[test]
+    fn nested_graph_overlaps() {
+        let inner = sample_blob(v3());
+        let outer_graph = ModuleGraph::parse(&inner).unwrap();
+        let mut builder = GraphBuilder::from_graph(&outer_graph);
+        builder.modules[1].contents.data = Cow::Owned(inner.clone());
+        let outer = builder.build().unwrap();
+        let (buf, starts) = join(&[b"junk", &outer]);
+        let hits = scan_graphs(&buf);
+        assert_eq!(hits.len(), 2);
+        // The embedded graph's trailer comes first in the file.
+        assert_eq!(hits[0].status, ScanStatus::Ok);
+        assert_eq!(hits[0].range.as_ref().unwrap().len(), inner.len());
+        assert_eq!(hits[1].status, ScanStatus::Overlaps { with: 0 });
+        assert_eq!(hits[1].range, Some(starts[1]..buf.len()));
+    }
+
+    #[test]
+    fn partial_graph_and_bad_byte_count_are_invalid() {
+        let blob = sample_blob(v3());
+        let bad = with_offsets(v3(), |off| off.byte_count = u32::MAX as u64);
+        // The first graph lost its start, the second points before the file.
+        let (buf, starts) = join(&[&blob[40..], b"junk", &bad]);
+        let hits = scan_graphs(&buf);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].trailer_offset, blob.len() - 40 - BUN_TRAILER.len());
+        assert_eq!(hits[1].trailer_offset, starts[2] + bad.len() - BUN_TRAILER.len());
+        for hit in &hits {
+            assert!(matches!(hit.status, ScanStatus::Invalid(_)), "{}", hit.status);
+            assert_eq!((hit.range.as_ref(), hit.layout, hit.modules), (None, None, 0));
+            assert!(hit.graph(&buf).is_none());
+        }
+        assert_eq!(format_scan_table(&hits).last().unwrap(), "2 trailers, 0 graphs");
+    }
+
+    #[test]
+    fn trailer_string_alone_is_invalid() {
+        let hits = scan_graphs(&[b"x".repeat(8).as_slice(), BUN_TRAILER, BUN_TRAILER].concat());
+        assert_eq!(hits.iter().map(|h| h.trailer_offset).collect::<Vec<_>>(), [8, 8 + BUN_TRAILER.len()]);
+        assert!(hits.iter().all(|h| matches!(h.status, ScanStatus::Invalid(_))));
+        assert!(scan_graphs(b"no trailer here").is_empty());
+    }
+}
@@ -0,0 +1,461 @@
+//! Source Map v3 model for the per-module `sourcemap` part, and the compact
+//! binary form newer Bun versions embed instead of JSON.
+//!
+//! **Interaction**: `SourceMap::from_bytes` reads a module's sourcemap bytes in
+//! either form; `sources` uses `sources` / `sourcesContent` to recover the
+//! original files and `unpack` writes the JSON conversion of a serialized map
+//! next to its raw bytes. Only the fields this tool needs are modeled; unknown
+//! JSON fields are ignored.
+//!
+//! Serialized layout (Bun `SerializedSourceMap`, all little-endian), with every
+//! StringPointer relative to the start of the map:
+//!
+//! | offset | field |
+//! |--------|-------|
+//! | 0      | `source_files_count: u32` |
+//! | 4      | `map_bytes_length: u32` |
+//! | 8      | `source_files_count` × StringPointer: source file names |
+//! | …      | `source_files_count` × StringPointer: zstd-compressed contents |
+//! | …      | `map_bytes_length` bytes: VLQ `mappings` |
+//! | …      | string data the pointers refer to |
+
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SourceMapError;
+use crate::graph::{read_u32_le, slice_at, StringPointer};
+
+/// Size of the serialized map header (`source_files_count`, `map_bytes_length`).
+pub const SERIALIZED_HEADER_SIZE: usize = 8;
+
+/// A Source Map v3 document.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceMap {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_root: Option<String>,
+    #[serde(default)]
+    pub sources: Vec<Option<String>>,
+    /// Parallel to `sources`; `None` entries (or a short array) mean no content.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources_content: Vec<Option<String>>,
+    #[serde(default)]
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub mappings: String,
+}
+
+impl SourceMap {
+    /// Parses a JSON sourcemap; `None` if `bytes` is not one.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+
+    /// Parses a sourcemap in either form: JSON, or Bun's serialized layout
+    /// (decoded with `from_serialized`). `None` if it is neither or fails to decode.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if let Some(map) = Self::parse(bytes) {
+            return Some(map);
+        }
+        SerializedSourceMap::parse(bytes).and_then(|s| Self::from_serialized(&s).ok())
+    }
+
+    /// Converts a serialized map: decompresses every source, and checks that
+    /// the VLQ mappings decode and only reference existing sources.
+    pub fn from_serialized(map: &SerializedSourceMap<'_>) -> Result<Self, SourceMapError> {
+        let count = map.source_files_count();
+        let mut sources = Vec::with_capacity(count);
+        let mut sources_content = Vec::with_capacity(count);
+        for i in 0..count {
+            sources.push(Some(String::from_utf8_lossy(map.source_name(i)).into_owned()));
+            let compressed = map.compressed_source(i);
+            if compressed.is_empty() {
+                sources_content.push(None);
+                continue;
+            }
+            let content = zstd_decompress(compressed).map_err(|reason| SourceMapError::Zstd {
+                source_index: i,
+                reason,
+            })?;
+            sources_content.push(Some(String::from_utf8_lossy(&content).into_owned()));
+        }
+        let mappings = std::str::from_utf8(map.mappings())
+            .map_err(|e| SourceMapError::BadVlq { at: e.valid_up_to() })?
+            .to_string();
+        for m in decode_mappings(&mappings)? {
+            if let Some(index) = m.source.filter(|&s| s as usize >= count) {
+                return Err(SourceMapError::SourceIndexOutOfRange { index, count });
+            }
+        }
+        Ok(Self {
+            version: 3,
+            sources,
+            sources_content,
+            mappings,
+            ..Self::default()
+        })
+    }
+
+    /// `(source, content)` pairs for every named source with embedded content,
+    /// with `sourceRoot` prepended to the source name.
+    pub fn embedded_sources(&self) -> impl Iterator<Item = (String, &str)> + '_ {
+        let root = self.source_root.as_deref().unwrap_or("");
+        self.sources.iter().enumerate().filter_map(move |(i, source)| {
+            let content = self.sources_content.get(i)?.as_deref()?;
+            let source = source.as_deref()?;
+            let sep = if root.is_empty() || root.ends_with('/') { "" } else { "/" };
+            Some((format!("{}{}{}", root, sep, source), content))
+        })
+    }
+}
+
+/// A sourcemap in Bun's compact serialized layout, borrowed from the blob.
+#[derive(Clone, Copy, Debug)]
+pub struct SerializedSourceMap<'a> {
+    bytes: &'a [u8],
+    source_files_count: usize,
+    map_bytes_length: usize,
+}
+
+impl<'a> SerializedSourceMap<'a> {
+    /// Recognizes the serialized layout: header, pointer tables and mappings
+    /// fit, and every pointer stays inside `bytes`. JSON maps (starting with
+    /// `{`) are never taken for one.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < SERIALIZED_HEADER_SIZE || bytes[0] == b'{' {
+            return None;
+        }
+        let source_files_count = read_u32_le(bytes, 0) as usize;
+        let map_bytes_length = read_u32_le(bytes, 4) as usize;
+        let tables_end = source_files_count
+            .checked_mul(16)?
+            .checked_add(SERIALIZED_HEADER_SIZE)?;
+        if tables_end.checked_add(map_bytes_length)? > bytes.len() {
+            return None;
+        }
+        let map = Self {
+            bytes,
+            source_files_count,
+            map_bytes_length,
+        };
+        let in_bounds = (0..source_files_count * 2).all(|i| map.pointer(i).end() <= bytes.len() as u64);
+        in_bounds.then_some(map)
+    }
+
+    pub fn source_files_count(&self) -> usize {
+        self.source_files_count
+    }
+
+    /// Name of source `i` as stored (usually a path relative to the project root).
+    pub fn source_name(&self, i: usize) -> &'a [u8] {
+        self.string(self.pointer(i))
+    }
+
+    /// zstd-compressed contents of source `i`; empty if not embedded.
+    pub fn compressed_source(&self, i: usize) -> &'a [u8] {
+        self.string(self.pointer(self.source_files_count + i))
+    }
+
+    /// Raw VLQ `mappings` bytes.
+    pub fn mappings(&self) -> &'a [u8] {
+        let start = SERIALIZED_HEADER_SIZE + self.source_files_count * 16;
+        &self.bytes[start..start + self.map_bytes_length]
+    }
+
+    /// `i`-th StringPointer after the header (names first, then contents).
+    fn pointer(&self, i: usize) -> StringPointer {
+        StringPointer::read(self.bytes, SERIALIZED_HEADER_SIZE + i * 8)
+    }
+
+    fn string(&self, p: StringPointer) -> &'a [u8] {
+        slice_at(self.bytes, p.offset as usize, p.length as usize).unwrap_or_default()
+    }
+}
+
+fn zstd_decompress(compressed: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoder = ruzstd::decoding::StreamingDecoder::new(compressed).map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// One decoded segment of `mappings`. Lines and columns are 0-based.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    pub generated_line: u32,
+    pub generated_column: u32,
+    /// Index into `sources`; `None` for a segment with only a generated column.
+    pub source: Option<u32>,
+    pub original_line: u32,
+    pub original_column: u32,
+    /// Index into `names`.
+    pub name: Option<u32>,
+}
+
+/// Decodes the Base64 VLQ `mappings` string into absolute positions, in order.
+pub fn decode_mappings(mappings: &str) -> Result<Vec<Mapping>, SourceMapError> {
+    let bytes = mappings.as_bytes();
+    let mut out = Vec::new();
+    let mut line = 0u32;
+    // Running values: the generated column resets per line, the rest carry over.
+    let mut column = 0i64;
+    let mut state = [0i64; 4]; // source, original line, original column, name
+    let mut at = 0;
+    while at < bytes.len() {
+        match bytes[at] {
+            b';' => {
+                line += 1;
+                column = 0;
+                at += 1;
+                continue;
+            }
+            b',' => {
+                at += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let segment_start = at;
+        let mut fields = [0i64; 5];
+        let mut n = 0;
+        while at < bytes.len() && bytes[at] != b',' && bytes[at] != b';' {
+            if n == fields.len() {
+                return Err(SourceMapError::BadVlq { at });
+            }
+            fields[n] = decode_vlq(bytes, &mut at)?;
+            n += 1;
+        }
+        if !matches!(n, 1 | 4 | 5) {
+            return Err(SourceMapError::BadVlq { at: segment_start });
+        }
+        let overflow = || SourceMapError::BadVlq { at: segment_start };
+        column = column.checked_add(fields[0]).ok_or_else(overflow)?;
+        for (s, delta) in state.iter_mut().zip(&fields[1..n]) {
+            *s = s.checked_add(*delta).ok_or_else(overflow)?;
+        }
+        let value = |v: i64| u32::try_from(v).map_err(|_| SourceMapError::BadVlq { at: segment_start });
+        out.push(Mapping {
+            generated_line: line,
+            generated_column: value(column)?,
+            source: if n >= 4 { Some(value(state[0])?) } else { None },
+            original_line: if n >= 4 { value(state[1])? } else { 0 },
+            original_column: if n >= 4 { value(state[2])? } else { 0 },
+            name: if n == 5 { Some(value(state[3])?) } else { None },
+        });
+    }
+    Ok(out)
+}
+
+/// Decodes one Base64 VLQ value starting at `*at` and advances past it.
+fn decode_vlq(bytes: &[u8], at: &mut usize) -> Result<i64, SourceMapError> {
+    let start = *at;
+    let mut value = 0i64;
+    let mut shift = 0;
+    loop {
+        let digit = bytes
+            .get(*at)
+            .and_then(|&b| base64_value(b))
+            .ok_or(SourceMapError::BadVlq { at: *at })?;
+        *at += 1;
+        if shift > 60 {
+            return Err(SourceMapError::BadVlq { at: start });
+        }
+        value |= ((digit & 0x1f) as i64) << shift;
+        shift += 5;
+        if digit & 0x20 == 0 {
+            break;
+        }
+    }
+    Ok(if value & 1 == 1 { -(value >> 1) } else { value >> 1 })
+}
+
+fn base64_value(b: u8) -> Option<u8> {
+    match b {
+        b'A'..=b'Z' => Some(b - b'A'),
+        b'a'..=b'z' => Some(b - b'a' + 26),
+        b'0'..=b'9' => Some(b - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn segment(line: u32, column: u32, original: Option<(u32, u32, u32)>, name: Option<u32>) -> Mapping {
+        let (source, original_line, original_column) = match original {
+            Some((s, l, c)) => (Some(s), l, c),
+            None => (None, 0, 0),
+        };
+        Mapping {
+            generated_line: line,
+            generated_column: column,
+            source,
+            original_line,
+            original_column,
+            name,
+        }
+    }
+
+    #[test]
+    fn vlq_values() {
+        let mut at = 0;
+        // 'A' = 0, 'C' = +1, 'D' = -1, "gB" = +16 ('g' has the continuation bit).
+        let bytes = b"ACDgB";
+        let values: Vec<i64> = (0..4).map(|_| decode_vlq(bytes, &mut at).unwrap()).collect();
+        assert_eq!(values, [0, 1, -1, 16]);
+        assert_eq!(at, bytes.len());
+    }
+
+    #[test]
+    fn relative_values_accumulate() {
+        // The second segment moves the original line and column back with negative deltas.
+        let mappings = decode_mappings("AAEE,CADD").unwrap();
+        assert_eq!(mappings, [segment(0, 0, Some((0, 2, 2)), None), segment(0, 1, Some((0, 1, 1)), None)]);
+    }
+
+    #[test]
+    fn semicolon_resets_only_the_generated_column() {
+        let mappings = decode_mappings("AAAA,EAAE;CAAC;;gBAAA").unwrap();
+        assert_eq!(
+            mappings,
+            [
+                segment(0, 0, Some((0, 0, 0)), None),
+                segment(0, 2, Some((0, 0, 2)), None),
+                segment(1, 1, Some((0, 0, 3)), None),
+                segment(3, 16, Some((0, 0, 3)), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_and_five_field_segments() {
+        let mappings = decode_mappings("AAAAA,C,CACAC").unwrap();
+        assert_eq!(
+            mappings,
+            [
+                segment(0, 0, Some((0, 0, 0)), Some(0)),
+                segment(0, 1, None, None),
+                segment(0, 2, Some((0, 1, 0)), Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_mappings() {
+        let bad = |s: &str| decode_mappings(s).unwrap_err();
+        assert_eq!(bad("AA!A"), SourceMapError::BadVlq { at: 2 });
+        assert_eq!(bad("AAAA,AA"), SourceMapError::BadVlq { at: 5 }, "two fields");
+        assert_eq!(bad("AAAAAA"), SourceMapError::BadVlq { at: 5 }, "six fields");
+        assert_eq!(bad("AAAg"), SourceMapError::BadVlq { at: 4 }, "unterminated VLQ");
+        assert_eq!(bad("AADA"), SourceMapError::BadVlq { at: 0 }, "negative original line");
+        assert_eq!(bad("gggggggggggggB"), SourceMapError::BadVlq { at: 0 }, "VLQ overflow");
+        // 2^61 and -2^61 (the largest magnitudes a VLQ holds) never wrap around.
+        let huge = "ggggggggggggE";
+        let neg_huge = "hgggggggggggE";
+        assert_eq!(bad(huge), SourceMapError::BadVlq { at: 0 }, "column past u32");
+        assert_eq!(bad(&format!("A{}AA", neg_huge)), SourceMapError::BadVlq { at: 0 }, "negative source");
+        assert_eq!(bad(&format!("AAAA,A{0}{0}{0}", huge)), SourceMapError::BadVlq { at: 5 }, "source past u32");
+    }
+
+    /// zstd frame storing `data` in one raw block (no compression, no checksum).
+    pub(crate) fn zstd_raw(data: &[u8]) -> Vec<u8> {
+        assert!(data.len() < 256);
+        let mut out = vec![0x28, 0xb5, 0x2f, 0xfd, 0x20, data.len() as u8];
+        let block = 1 | (data.len() as u32) << 3;
+        out.extend_from_slice(&block.to_le_bytes()[..3]);
+        out.extend_from_slice(data);
+        out
+    }
+
+    /// Serialized map with one source per `(name, content)`; empty content is
+    /// stored as a (0, 0) pointer.
+    pub(crate) fn serialized(sources: &[(&str, &str)], mappings: &str) -> Vec<u8> {
+        let count = sources.len();
+        let mut strings = Vec::new();
+        let mut pointers = Vec::new();
+        let data_start = SERIALIZED_HEADER_SIZE + count * 16 + mappings.len();
+        let mut add = |bytes: &[u8], pointers: &mut Vec<u8>| {
+            let offset = if bytes.is_empty() { 0 } else { data_start + strings.len() };
+            pointers.extend_from_slice(&(offset as u32).to_le_bytes());
+            pointers.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            strings.extend_from_slice(bytes);
+        };
+        for (name, _) in sources {
+            add(name.as_bytes(), &mut pointers);
+        }
+        for (_, content) in sources {
+            let compressed = if content.is_empty() { Vec::new() } else { zstd_raw(content.as_bytes()) };
+            add(&compressed, &mut pointers);
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(&(count as u32).to_le_bytes());
+        out.extend_from_slice(&(mappings.len() as u32).to_le_bytes());
+        out.extend_from_slice(&pointers);
+        out.extend_from_slice(mappings.as_bytes());
+        out.extend_from_slice(&strings);
+        out
+    }
+
+    #[test]
+    fn serialized_map_converts_to_json_form() {
+        let bytes = serialized(&[("src/a.ts", "export const a = 1;\n"), ("src/b.ts", "")], "AAAA;ACAA");
+        let raw = SerializedSourceMap::parse(&bytes).unwrap();
+        assert_eq!(raw.source_files_count(), 2);
+        assert_eq!(raw.source_name(1), b"src/b.ts");
+        assert_eq!(raw.mappings(), b"AAAA;ACAA");
+        assert!(raw.compressed_source(1).is_empty());
+
+        let map = SourceMap::from_bytes(&bytes).unwrap();
+        assert_eq!(map.version, 3);
+        assert_eq!(map.sources, [Some("src/a.ts".to_string()), Some("src/b.ts".to_string())]);
+        assert_eq!(map.sources_content, [Some("export const a = 1;\n".to_string()), None]);
+        assert_eq!(map.mappings, "AAAA;ACAA");
+        let embedded: Vec<_> = map.embedded_sources().collect();
+        assert_eq!(embedded, [("src/a.ts".to_string(), "export const a = 1;\n")]);
+    }
+
+    #[test]
+    fn serialized_map_errors() {
+        let bytes = serialized(&[("a.ts", "")], "ACAA");
+        let map = SerializedSourceMap::parse(&bytes).unwrap();
+        assert_eq!(
+            SourceMap::from_serialized(&map).unwrap_err(),
+            SourceMapError::SourceIndexOutOfRange { index: 1, count: 1 }
+        );
+
+        let bytes = serialized(&[("a.ts", "x")], "AA");
+        let map = SerializedSourceMap::parse(&bytes).unwrap();
+        assert_eq!(SourceMap::from_serialized(&map).unwrap_err(), SourceMapError::BadVlq { at: 0 });
+
+        let mut bytes = serialized(&[("a.ts", "x")], "AAAA");
+        let last = bytes.len() - 1;
+        bytes[last - 9] = 0; // corrupt the zstd magic
+        let map = SerializedSourceMap::parse(&bytes).unwrap();
+        assert!(matches!(
+            SourceMap::from_serialized(&map).unwrap_err(),
+            SourceMapError::Zstd { source_index: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn serialized_map_rejects_bad_bounds_and_json() {
+        let mut bytes = serialized(&[("a.ts", "")], "AAAA");
+        bytes[4..8].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(SerializedSourceMap::parse(&bytes).is_none(), "mappings past the end");
+
+        let mut bytes = serialized(&[("a.ts", "")], "AAAA");
+        bytes[12..16].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(SerializedSourceMap::parse(&bytes).is_none(), "name pointer past the end");
+
+        let json = br#"{"version":3,"sources":["a.ts"],"mappings":"AAAA"}"#;
+        assert!(SerializedSourceMap::parse(json).is_none());
+        assert_eq!(SourceMap::from_bytes(json).unwrap().sources, [Some("a.ts".to_string())]);
+    }
+}
@@ -0,0 +1,229 @@
+//! Recover original sources (e.g. TypeScript) from each module's sourcemap
+//! `sourcesContent` into a `sources/` tree.
+//!
+//! **Interaction**: Called by `unpack::write_modules` when
+//! `UnpackOptions::sources` is set; the returned entries go into
+//! `manifest.json` as `sources`. The same original file referenced by several
+//! modules is written once; different contents under one normalized path get
+//! a `~N` suffix so nothing is overwritten.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{Module, ModuleGraph};
+use crate::manifest::sha256_hex;
+use crate::sourcemap::SourceMap;
+
+/// Directory under the output directory that recovered sources are written to.
+pub const SOURCES_DIR: &str = "sources";
+
+/// One original source written under `sources/`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceEntry {
+    /// Path relative to the output directory (`sources/...`).
+    pub file: String,
+    /// Source name as it appears in the sourcemap (with `sourceRoot`).
+    pub source: String,
+    pub size: usize,
+    pub sha256: String,
+    /// Indices of the modules whose sourcemap contains this source.
+    pub modules: Vec<usize>,
+}
+
+/// Writes the embedded sources of every selected module's sourcemap to
+/// `out_dir/sources/`. Both JSON and serialized sourcemaps are read; ones that
+/// do not decode are skipped.
+pub fn write_sources(
+    graph: &ModuleGraph<'_>,
+    out_dir: &Path,
+    select: impl Fn(&Module<'_>) -> bool,
+) -> Result<Vec<SourceEntry>> {
+    let mut entries: Vec<SourceEntry> = Vec::new();
+    // Normalized path -> indices into `entries` written under it (one per distinct content).
+    let mut by_path: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for module in graph.modules.iter().filter(|m| select(m)) {
+        let Some(map) = SourceMap::from_bytes(module.sourcemap) else {
+            continue;
+        };
+        for (source, content) in map.embedded_sources() {
+            let rel = normalize_source_path(&source);
+            if rel.is_empty() {
+                continue;
+            }
+            let sha256 = sha256_hex(content.as_bytes());
+            let variants = by_path.entry(rel.clone()).or_default();
+            if let Some(&i) = variants.iter().find(|&&i| entries[i].sha256 == sha256) {
+                if !entries[i].modules.contains(&module.index) {
+                    entries[i].modules.push(module.index);
+                }
+                continue;
+            }
+            let (file, full) = free_path(out_dir, SOURCES_DIR, &rel, variants.len());
+            if let Some(parent) = full.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("create dir {:?}", parent))?;
+            }
+            fs::write(&full, content).with_context(|| format!("write source {:?}", full))?;
+            variants.push(entries.len());
+            entries.push(SourceEntry {
+                file,
+                source,
+                size: content.len(),
+                sha256,
+                modules: vec![module.index],
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Maps a sourcemap source name to a safe relative path: drops URL schemes
+/// (`webpack://`, `file://`), drive letters, `.` / `..` and empty components.
+/// Returns an empty string if nothing is left or the name contains NUL.
+pub fn normalize_source_path(source: &str) -> String {
+    if source.contains('\0') {
+        return String::new();
+    }
+    let s = match source.find("://") {
+        Some(i) => &source[i + 3..],
+        None => source,
+    };
+    s.split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != "." && *c != ".." && !c.ends_with(':'))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// First `<dir>/` path for `rel` (from variant `n` on) that is neither an
+/// existing directory nor below an existing file, e.g. `a` next to `a/b.ts`.
+/// The `~N` suffix goes on the file name, or on a directory component when a
+/// file already sits at that path. Returns the relative and the full path.
+pub(crate) fn free_path(out_dir: &Path, dir: &str, rel: &str, n: usize) -> (String, PathBuf) {
+    let base = out_dir.join(dir);
+    let parts: Vec<&str> = rel.split('/').collect();
+    for n in n.. {
+        for k in (0..parts.len()).rev() {
+            let mut candidate = parts.clone();
+            let suffixed = with_variant_suffix(parts[k], n);
+            candidate[k] = &suffixed;
+            let full = base.join(candidate.join("/"));
+            let blocked = full.is_dir() || (1..candidate.len()).any(|i| base.join(candidate[..i].join("/")).is_file());
+            if !blocked {
+                return (format!("{}/{}", dir, candidate.join("/")), full);
+            }
+            if n == 0 {
+                break;
+            }
+        }
+    }
+    unreachable!("some variant suffix is always free")
+}
+
+/// `a.ts` with `n = 0` stays as is; `n = 1` becomes `a~1.ts`.
+pub(crate) fn with_variant_suffix(name: &str, n: usize) -> String {
+    if n == 0 {
+        return name.to_string();
+    }
+    match name.rfind('.').filter(|&i| i > 0) {
+        Some(dot) => format!("{}~{}{}", &name[..dot], n, &name[dot..]),
+        None => format!("{}~{}", name, n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::layout::LAYOUTS;
+    use crate::pack::GraphBuilder;
+
+    #[test]
+    fn normalized_paths_stay_inside_sources() {
+        let cases = [
+            ("../src/cli.ts", "src/cli.ts"),
+            ("../../../../etc/passwd", "etc/passwd"),
+            ("src/./a/../b.ts", "src/a/b.ts"),
+            ("/home/user/app/index.ts", "home/user/app/index.ts"),
+            ("webpack://app/./src/a.ts", "app/src/a.ts"),
+            ("file:///C:/work/a.ts", "work/a.ts"),
+            ("C:\\Users\\dev\\..\\a.ts", "Users/dev/a.ts"),
+            ("\\\\server\\share\\a.ts", "server/share/a.ts"),
+            ("bun:wrap", "bun:wrap"),
+            ("bun://internal/../x.js", "internal/x.js"),
+            ("..", ""),
+            ("a\0b.ts", ""),
+        ];
+        for (source, expected) in cases {
+            let rel = normalize_source_path(source);
+            assert_eq!(rel, expected, "{:?}", source);
+            assert!(!rel.starts_with('/') && !rel.split('/').any(|c| c == ".." || c == "."), "{:?}", rel);
+        }
+    }
+
+    fn sourcemap(sources: &[&str], contents: &[&str]) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "version": 3,
+            "sources": sources,
+            "sourcesContent": contents,
+            "names": [],
+            "mappings": "AAAA",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn dedupes_identical_sources_and_suffixes_different_ones() {
+        let blob = sample_blob(&LAYOUTS[0]);
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.modules[0].sourcemap.data =
+            Cow::Owned(sourcemap(&["../src/a.ts", "webpack://app/./b.ts"], &["let a = 1;", "let b = 1;"]));
+        builder.modules[1].sourcemap.data = Cow::Owned(sourcemap(&["src/a.ts", "app/b.ts"], &["let a = 1;", "let b = 2;"]));
+        let blob = builder.build().unwrap();
+        let graph = ModuleGraph::parse(&blob).unwrap();
+
+        let out = std::env::temp_dir().join(format!("bun-unpack-sources-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out);
+        let entries = write_sources(&graph, &out, |_| true).unwrap();
+        let summary: Vec<_> = entries.iter().map(|e| (e.file.as_str(), e.modules.clone())).collect();
+        assert_eq!(
+            summary,
+            [("sources/src/a.ts", vec![0, 1]), ("sources/app/b.ts", vec![0]), ("sources/app/b~1.ts", vec![1])]
+        );
+        assert_eq!(fs::read_to_string(out.join("sources/app/b.ts")).unwrap(), "let b = 1;");
+        assert_eq!(fs::read_to_string(out.join("sources/app/b~1.ts")).unwrap(), "let b = 2;");
+        assert_eq!(entries[2].source, "app/b.ts");
+        assert_eq!(entries[2].sha256, sha256_hex(b"let b = 2;"));
+
+        // Only selected modules are read.
+        let _ = fs::remove_dir_all(&out);
+        let entries = write_sources(&graph, &out, |m| m.index == 1).unwrap();
+        assert_eq!(entries.iter().map(|e| e.file.as_str()).collect::<Vec<_>>(), ["sources/src/a.ts", "sources/app/b.ts"]);
+        fs::remove_dir_all(&out).unwrap();
+    }
+
+    #[test]
+    fn free_path_avoids_files_and_directories() {
+        let out = std::env::temp_dir().join(format!("bun-unpack-free-path-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out);
+        fs::create_dir_all(out.join("sources/lib/util")).unwrap();
+        fs::write(out.join("sources/a"), "").unwrap();
+
+        assert_eq!(free_path(&out, SOURCES_DIR, "b.ts", 0).0, "sources/b.ts");
+        assert_eq!(free_path(&out, SOURCES_DIR, "b.ts", 2).0, "sources/b~2.ts");
+        // `lib/util` is a directory, so the file gets a suffix.
+        assert_eq!(free_path(&out, SOURCES_DIR, "lib/util", 0).0, "sources/lib/util~1");
+        // `a` is a file, so the directory component gets one.
+        let (rel, full) = free_path(&out, SOURCES_DIR, "a/b.ts", 0);
+        assert_eq!(rel, "sources/a~1/b.ts");
+        assert_eq!(full, out.join("sources/a~1/b.ts"));
+        assert_eq!(with_variant_suffix(".env", 1), ".env~1");
+        fs::remove_dir_all(&out).unwrap();
+    }
+}
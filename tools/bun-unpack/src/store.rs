@@ -0,0 +1,425 @@
+//! Content-addressed store: every module part is kept once under its SHA-256,
+//! with one manifest per executable pointing at the hashes.
+//!
+//! **Interaction**: An alternative to `unpack::write_modules` for the default
+//! mode and `batch` (CLI `--store`), and read by the CLI `query` subcommand.
+//! Layout under the store root:
+//!
+//! - `objects/<2 hex>/<62 hex>`: a part's bytes, written once.
+//! - `builds/<name>.json`: the build's `Manifest`; each part's `file` is its
+//!   object path relative to the store root.
+//! - `refs/<2 hex>/<62 hex>`: one JSON line per occurrence of the object (build,
+//!   module path, part), so "which builds contain this file" reads one file.
+//!
+//! Adding a build under an existing name is refused unless the store was
+//! opened with `replace_builds`; then the old manifest and its refs are
+//! replaced. Objects are written to a temporary name and hard-linked into
+//! place, which fails if another worker got there first, and manifest / ref
+//! updates hold a lock, so one `Store` can be shared by `batch` workers.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::graph::ModuleGraph;
+use crate::manifest::{Manifest, PartEntry, SourceInfo};
+
+pub const OBJECTS_DIR: &str = "objects";
+pub const BUILDS_DIR: &str = "builds";
+pub const REFS_DIR: &str = "refs";
+
+/// One occurrence of an object in a build.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StoreRef {
+    pub build: String,
+    /// Module path (`rel_path`) in that build.
+    pub path: String,
+    /// `contents`, `sourcemap` or `bytecode`.
+    pub part: String,
+}
+
+/// What `Store::add_graph` did.
+#[derive(Clone, Debug)]
+pub struct StoreAdd {
+    /// The manifest written to `builds/<name>.json`.
+    pub manifest: Manifest,
+    /// Parts referenced by the build.
+    pub parts: usize,
+    /// Objects this call wrote (not in the store yet), and their total size.
+    pub new_objects: usize,
+    pub new_bytes: u64,
+    /// An existing build of the same name was replaced.
+    pub replaced: bool,
+}
+
+/// A store rooted at a directory (created on open).
+pub struct Store {
+    root: PathBuf,
+    /// `add_graph` may replace an existing build.
+    replace: bool,
+    /// Serializes manifest and ref updates between threads.
+    lock: Mutex<()>,
+}
+
+impl Store {
+    pub fn open(root: &Path) -> Result<Self> {
+        for dir in [OBJECTS_DIR, BUILDS_DIR, REFS_DIR] {
+            let path = root.join(dir);
+            fs::create_dir_all(&path).with_context(|| format!("create store dir {:?}", path))?;
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            replace: false,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Lets `add_graph` replace an existing build of the same name instead of
+    /// failing.
+    pub fn replace_builds(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Object path relative to the store root, e.g. `objects/ab/cdef...`.
+    pub fn object_rel_path(sha256: &str) -> String {
+        format!("{}/{}/{}", OBJECTS_DIR, &sha256[..2], &sha256[2..])
+    }
+
+    /// Stores every part of `graph` and writes its manifest as build `name`
+    /// (a relative path such as `nightly/2025-01-01/app.exe`). Fails if the
+    /// build exists, unless the store replaces builds.
+    pub fn add_graph(&self, name: &str, graph: &ModuleGraph<'_>, source: SourceInfo) -> Result<StoreAdd> {
+        let manifest_path = self.build_path(name)?;
+        self.check_replace(name, &manifest_path)?;
+        let mut manifest = Manifest::from_graph(graph, source);
+        let mut refs = Vec::new();
+        let mut new_objects = 0;
+        let mut new_bytes = 0;
+
+        for (module, entry) in graph.modules.iter().zip(&mut manifest.modules) {
+            entry.sourcemap_json = None;
+            let parts = [
+                ("contents", module.contents, &mut entry.contents),
+                ("sourcemap", module.sourcemap, &mut entry.sourcemap),
+                ("bytecode", module.bytecode, &mut entry.bytecode),
+            ];
+            for (part, bytes, part_entry) in parts {
+                let Some(part_entry) = part_entry.as_mut().filter(|_| !bytes.is_empty()) else {
+                    continue;
+                };
+                if self.put_object(&part_entry.sha256, bytes)? {
+                    new_objects += 1;
+                    new_bytes += bytes.len() as u64;
+                }
+                part_entry.file = Some(Self::object_rel_path(&part_entry.sha256));
+                refs.push((
+                    part_entry.sha256.clone(),
+                    StoreRef {
+                        build: name.to_string(),
+                        path: entry.rel_path.clone(),
+                        part: part.to_string(),
+                    },
+                ));
+            }
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        // Checked again: another worker may have added the build meanwhile.
+        self.check_replace(name, &manifest_path)?;
+        let replaced = manifest_path.exists();
+        if replaced {
+            let old = read_manifest(&manifest_path)?;
+            for sha256 in manifest_objects(&old) {
+                self.edit_refs(&sha256, |refs| refs.retain(|r| r.build != name))?;
+            }
+        }
+        if let Some(parent) = manifest_path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create dir {:?}", parent))?;
+        }
+        let json = serde_json::to_vec_pretty(&manifest).context("serialize manifest")?;
+        fs::write(&manifest_path, json).with_context(|| format!("write {:?}", manifest_path))?;
+        let parts = refs.len();
+        for (sha256, r) in refs {
+            self.edit_refs(&sha256, |refs| {
+                if !refs.contains(&r) {
+                    refs.push(r);
+                }
+            })?;
+        }
+        Ok(StoreAdd {
+            manifest,
+            parts,
+            new_objects,
+            new_bytes,
+            replaced,
+        })
+    }
+
+    fn check_replace(&self, name: &str, manifest_path: &Path) -> Result<()> {
+        if !self.replace && manifest_path.exists() {
+            anyhow::bail!("build {:?} is already in the store (pass --replace, or pick another --name)", name);
+        }
+        Ok(())
+    }
+
+    /// Every occurrence of the object `sha256`, in the order they were added.
+    pub fn lookup(&self, sha256: &str) -> Result<Vec<StoreRef>> {
+        if !is_sha256(sha256) {
+            anyhow::bail!("{:?} is not a SHA-256 hex digest", sha256);
+        }
+        read_refs(&self.ref_path(sha256))
+    }
+
+    /// Names of all builds in the store, sorted.
+    pub fn builds(&self) -> Result<Vec<String>> {
+        let dir = self.root.join(BUILDS_DIR);
+        let mut names = Vec::new();
+        for entry in walkdir::WalkDir::new(&dir).sort_by_file_name() {
+            let entry = entry.with_context(|| format!("walk {:?}", dir))?;
+            let rel = entry.path().strip_prefix(&dir).unwrap_or(entry.path());
+            if let Some(name) = rel.to_str().and_then(|s| s.strip_suffix(".json")) {
+                names.push(name.replace('\\', "/"));
+            }
+        }
+        Ok(names)
+    }
+
+    /// Reads the manifest of build `name`.
+    pub fn manifest(&self, name: &str) -> Result<Manifest> {
+        read_manifest(&self.build_path(name)?)
+    }
+
+    /// Writes `bytes` as object `sha256` unless it exists; returns whether this
+    /// call wrote it.
+    fn put_object(&self, sha256: &str, bytes: &[u8]) -> Result<bool> {
+        let path = self.root.join(Self::object_rel_path(sha256));
+        if path.exists() {
+            return Ok(false);
+        }
+        let dir = path.parent().expect("object path has a parent");
+        fs::create_dir_all(dir).with_context(|| format!("create dir {:?}", dir))?;
+        // Another worker may be writing the same object; each uses its own
+        // temporary file, and linking it into place fails for all but one, so
+        // readers never see a partial object and it is counted once.
+        let tmp = dir.join(format!(".{}.{:?}.tmp", &sha256[2..], std::thread::current().id()));
+        fs::write(&tmp, bytes).with_context(|| format!("write {:?}", tmp))?;
+        let linked = fs::hard_link(&tmp, &path);
+        fs::remove_file(&tmp).with_context(|| format!("remove {:?}", tmp))?;
+        match linked {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("link {:?} to {:?}", tmp, path)),
+        }
+    }
+
+    fn ref_path(&self, sha256: &str) -> PathBuf {
+        self.root.join(REFS_DIR).join(&sha256[..2]).join(&sha256[2..])
+    }
+
+    /// Rewrites the refs of `sha256` through `edit`; removes the file when none remain.
+    fn edit_refs(&self, sha256: &str, edit: impl FnOnce(&mut Vec<StoreRef>)) -> Result<()> {
+        let path = self.ref_path(sha256);
+        let mut refs = read_refs(&path)?;
+        edit(&mut refs);
+        if refs.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path).with_context(|| format!("remove {:?}", path))?;
+            }
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create dir {:?}", parent))?;
+        }
+        let mut out = Vec::new();
+        for r in &refs {
+            serde_json::to_writer(&mut out, r).context("serialize ref")?;
+            out.write_all(b"\n")?;
+        }
+        fs::write(&path, out).with_context(|| format!("write {:?}", path))
+    }
+
+    /// `builds/<name>.json`; `name` must be a relative path without `..`.
+    fn build_path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && Path::new(name).components().all(|c| matches!(c, Component::Normal(_)));
+        if !valid {
+            anyhow::bail!("invalid build name {:?} (must be a relative path without `..`)", name);
+        }
+        Ok(self.root.join(BUILDS_DIR).join(format!("{}.json", name)))
+    }
+}
+
+/// Objects referenced by `manifest`.
+fn manifest_objects(manifest: &Manifest) -> HashSet<String> {
+    let mut objects = HashSet::new();
+    for m in &manifest.modules {
+        let parts: [&Option<PartEntry>; 3] = [&m.contents, &m.sourcemap, &m.bytecode];
+        for part in parts.into_iter().flatten() {
+            if part.file.is_some() {
+                objects.insert(part.sha256.clone());
+            }
+        }
+    }
+    objects
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest> {
+    let json = fs::read(path).with_context(|| format!("read {:?}", path))?;
+    serde_json::from_slice(&json).with_context(|| format!("parse {:?}", path))
+}
+
+fn read_refs(path: &Path) -> Result<Vec<StoreRef>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("read {:?}", path)),
+    };
+    text.lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| serde_json::from_str(l).with_context(|| format!("parse ref in {:?}", path)))
+        .collect()
+}
+
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Formats the refs of `sha256` as one row per occurrence (build, part, module
+/// path), then a summary line.
+pub fn format_store_refs(sha256: &str, refs: &[StoreRef]) -> Vec<String> {
+    let mut lines = Vec::with_capacity(refs.len() + 2);
+    if refs.is_empty() {
+        lines.push(format!("{} is not in the store", sha256));
+        return lines;
+    }
+    let width = refs.iter().map(|r| r.build.len()).max().unwrap_or(0).max("build".len());
+    lines.push(format!("{:<width$}  {:<9}  {}", "build", "part", "path"));
+    for r in refs {
+        lines.push(format!("{:<width$}  {:<9}  {}", r.build, r.part, r.path));
+    }
+    let builds: HashSet<&str> = refs.iter().map(|r| r.build.as_str()).collect();
+    lines.push(format!("{}: {} occurrences in {} builds", sha256, refs.len(), builds.len()));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::layout::LAYOUTS;
+    use crate::manifest::{sha256_hex, ExecutableFormat};
+    use crate::pack::GraphBuilder;
+
+    fn temp_store(name: &str) -> (PathBuf, Store) {
+        let dir = std::env::temp_dir().join(format!("bun-unpack-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = Store::open(&dir).unwrap();
+        (dir, store)
+    }
+
+    fn source() -> SourceInfo {
+        SourceInfo {
+            path: None,
+            format: ExecutableFormat::Blob,
+            arch: None,
+            runtime: None,
+        }
+    }
+
+    /// `sample_blob` with `lib/util.js` changed to `util`.
+    fn variant(util: &'static [u8]) -> Vec<u8> {
+        let blob = sample_blob(&LAYOUTS[0]);
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.modules[1].contents.data = util.into();
+        builder.build().unwrap()
+    }
+
+    fn object_count(dir: &Path) -> usize {
+        walkdir::WalkDir::new(dir.join(OBJECTS_DIR))
+            .into_iter()
+            .filter(|e| e.as_ref().unwrap().file_type().is_file())
+            .count()
+    }
+
+    #[test]
+    fn shares_objects_across_builds() {
+        let (dir, store) = temp_store("share");
+        let (a, b) = (variant(b"export const x = 1;\n"), variant(b"export const x = 2;\n"));
+        let first = store.add_graph("nightly/1/app.exe", &ModuleGraph::parse(&a).unwrap(), source()).unwrap();
+        let second = store.add_graph("nightly/2/app.exe", &ModuleGraph::parse(&b).unwrap(), source()).unwrap();
+        // cli.js contents + sourcemap + bytecode, and util.js.
+        assert_eq!((first.parts, first.new_objects), (4, 4));
+        // Only the changed util.js is new.
+        assert_eq!((second.parts, second.new_objects), (4, 1));
+        assert_eq!(second.new_bytes, 20);
+        assert_eq!(object_count(&dir), 5);
+
+        let cli = sha256_hex(b"console.log(1);\n");
+        let builds: Vec<_> = store.lookup(&cli).unwrap().into_iter().map(|r| (r.build, r.path, r.part)).collect();
+        assert_eq!(
+            builds,
+            [
+                ("nightly/1/app.exe".to_string(), "root/cli.js".to_string(), "contents".to_string()),
+                ("nightly/2/app.exe".to_string(), "root/cli.js".to_string(), "contents".to_string()),
+            ]
+        );
+        assert_eq!(store.builds().unwrap(), ["nightly/1/app.exe", "nightly/2/app.exe"]);
+        let manifest = store.manifest("nightly/2/app.exe").unwrap();
+        let file = manifest.modules[0].contents.as_ref().unwrap().file.clone().unwrap();
+        assert_eq!(fs::read(dir.join(file)).unwrap(), b"console.log(1);\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replacing_a_build_drops_only_its_refs() {
+        let (dir, store) = temp_store("replace");
+        let (a, b) = (variant(b"export const x = 1;\n"), variant(b"export const x = 2;\n"));
+        let (ga, gb) = (ModuleGraph::parse(&a).unwrap(), ModuleGraph::parse(&b).unwrap());
+        store.add_graph("app-1", &ga, source()).unwrap();
+        store.add_graph("app-2", &ga, source()).unwrap();
+
+        // Same name again: refused by default.
+        let err = store.add_graph("app-1", &gb, source()).unwrap_err();
+        assert!(err.to_string().contains("already in the store"), "{err}");
+
+        let store = store.replace_builds(true);
+        let added = store.add_graph("app-1", &gb, source()).unwrap();
+        assert!(added.replaced);
+        let old_util = sha256_hex(b"export const x = 1;\n");
+        let new_util = sha256_hex(b"export const x = 2;\n");
+        let builds = |sha: &str| store.lookup(sha).unwrap().into_iter().map(|r| r.build).collect::<Vec<_>>();
+        assert_eq!(builds(&old_util), ["app-2"]);
+        assert_eq!(builds(&new_util), ["app-1"]);
+        assert_eq!(builds(&sha256_hex(b"console.log(1);\n")), ["app-2", "app-1"]);
+        // Objects are kept even when no build refers to them.
+        store.add_graph("app-2", &gb, source()).unwrap();
+        assert!(builds(&old_util).is_empty());
+        assert!(dir.join(Store::object_rel_path(&old_util)).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn put_object_counts_once() {
+        let (dir, store) = temp_store("put");
+        let sha = sha256_hex(b"abc");
+        let wrote: Vec<bool> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4).map(|_| s.spawn(|| store.put_object(&sha, b"abc").unwrap())).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(wrote.iter().filter(|&&w| w).count(), 1);
+        assert_eq!(object_count(&dir), 1);
+        assert!(store.add_graph("../x", &ModuleGraph::parse(&variant(b"")).unwrap(), source()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+//! Remove the module graph from an executable, leaving the Bun runtime it was
+//! compiled from.
+//!
+//! **Interaction**: Uses `extract::locate_bun_blob` like `inject`, and undoes
+//! what `bun build --compile` did per format. Used by the CLI `strip`
+//! subcommand, whose output can be hashed and compared with an official Bun
+//! release to check that the runtime itself was not modified.
+//!
+//! - ELF: the blob and its u64 length were appended to the runtime; truncating
+//!   them gives the runtime back byte for byte.
+//! - PE: `.bun` was added as the last section. Its header is removed and
+//!   NumberOfSections, SizeOfImage and SizeOfInitializedData are restored. When
+//!   it is not the last section (or data follows it) it is zeroed instead.
+//! - Mach-O: `__BUN,__bun` exists in the runtime and was resized in place, so
+//!   only its contents are zeroed; the result does not match a release.
+
+use anyhow::{Context, Result};
+use goblin::Object;
+use serde::Serialize;
+
+use crate::extract::{locate_bun_blob, BlobLocation, Container};
+use crate::graph::read_u32_le;
+use crate::inject::{
+    macho_has_code_signature, pe_certificate_table_size, pe_optional_header_offset, pe_resize_initialized_data,
+    pe_section_header_offset, write_u32_le, PE_SECTION_HEADER_SIZE,
+};
+
+/// How the payload was removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StripMethod {
+    /// ELF: blob and length cut off the end of the file.
+    Truncated,
+    /// PE: `.bun` section header and data removed.
+    SectionRemoved,
+    /// Section kept with its contents zeroed; headers unchanged.
+    Zeroed,
+}
+
+impl StripMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Truncated => "truncated",
+            Self::SectionRemoved => "section removed",
+            Self::Zeroed => "zeroed",
+        }
+    }
+}
+
+/// Result of `strip_blob`: the runtime bytes and what was done to get them.
+#[derive(Clone, Debug)]
+pub struct Stripped {
+    pub bytes: Vec<u8>,
+    /// Where the removed blob was in the input.
+    pub location: BlobLocation,
+    pub method: StripMethod,
+    /// Bytes removed from the file (0 when zeroed).
+    pub removed: usize,
+    /// Reasons the output will not match an official release byte for byte.
+    pub warnings: Vec<String>,
+}
+
+/// Removes the module graph from `exe`. `arch` selects the slice of a universal
+/// Mach-O, as in `locate_bun_blob`.
+pub fn strip_blob(exe: &[u8], arch: Option<&str>) -> Result<Stripped> {
+    let location = locate_bun_blob(exe, arch)?;
+    let mut warnings = Vec::new();
+    let (bytes, method) = match &location.container {
+        Container::ElfTrailer => (exe[..location.range.start].to_vec(), StripMethod::Truncated),
+        Container::PeSection { index, raw, .. } => strip_pe(exe, *index, raw.clone(), &mut warnings)?,
+        Container::MachOSection { raw, .. } => {
+            let mut out = exe.to_vec();
+            out[raw.clone()].fill(0);
+            warnings.push(
+                "Mach-O __BUN,__bun was resized when compiling; its contents are zeroed, but the output does not match the release"
+                    .to_string(),
+            );
+            if macho_has_code_signature(exe, raw.start) {
+                warnings.push("Mach-O code signature is now invalid".to_string());
+            }
+            (out, StripMethod::Zeroed)
+        }
+        Container::MachOTrailerScan => anyhow::bail!(
+            "Mach-O blob was found only by trailer search (no __BUN,__bun section); cannot strip"
+        ),
+    };
+    Ok(Stripped {
+        removed: exe.len() - bytes.len(),
+        bytes,
+        location,
+        method,
+        warnings,
+    })
+}
+
+/// Removes the `.bun` section when it is the last one in the section table, in
+/// the file and in memory; zeroes its raw data otherwise.
+fn strip_pe(
+    exe: &[u8],
+    index: usize,
+    raw: std::ops::Range<usize>,
+    warnings: &mut Vec<String>,
+) -> Result<(Vec<u8>, StripMethod)> {
+    let pe = match Object::parse(exe)? {
+        Object::PE(pe) => pe,
+        _ => anyhow::bail!("expected PE"),
+    };
+    let opt = pe.header.optional_header.context("PE has no optional header")?;
+    let section_alignment = opt.windows_fields.section_alignment.max(1) as usize;
+    let bun = &pe.sections[index];
+    if pe_certificate_table_size(exe) > 0 {
+        warnings.push("Authenticode signature is now invalid".to_string());
+    }
+
+    let last_in_table = index + 1 == pe.sections.len();
+    let last_in_file = pe.sections.iter().enumerate().all(|(i, s)| {
+        i == index || (s.pointer_to_raw_data as usize + s.size_of_raw_data as usize) <= raw.start
+    });
+    let last_in_memory = pe
+        .sections
+        .iter()
+        .enumerate()
+        .all(|(i, s)| i == index || s.virtual_address < bun.virtual_address);
+    if !(last_in_table && last_in_file && last_in_memory) || raw.end < exe.len() {
+        let mut out = exe.to_vec();
+        out[raw].fill(0);
+        warnings.push(
+            ".bun is not the last section or data follows it; its contents are zeroed, but the output does not match the release"
+                .to_string(),
+        );
+        return Ok((out, StripMethod::Zeroed));
+    }
+
+    let mut out = exe[..raw.start].to_vec();
+    let header = pe_section_header_offset(exe, index);
+    out[header..header + PE_SECTION_HEADER_SIZE].fill(0);
+    let coff = read_u32_le(exe, 0x3c) as usize + 4;
+    out[coff + 2..coff + 4].copy_from_slice(&(pe.sections.len() as u16 - 1).to_le_bytes());
+
+    pe_resize_initialized_data(&mut out, bun.characteristics, bun.size_of_raw_data, 0);
+    let image_end = pe
+        .sections
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != index)
+        .map(|(_, s)| {
+            let size = if s.virtual_size == 0 { s.size_of_raw_data } else { s.virtual_size };
+            (s.virtual_address as usize + size as usize).next_multiple_of(section_alignment)
+        })
+        .max()
+        .unwrap_or(0);
+    write_u32_le(&mut out, pe_optional_header_offset(exe) + 56, image_end as u32);
+    Ok((out, StripMethod::SectionRemoved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::inject::inject_blob;
+    use crate::inject::tests::{grown_blob, synthetic_macho, synthetic_pe};
+    use crate::layout::Layout;
+
+    #[test]
+    fn pe_strip_restores_runtime() {
+        let base = synthetic_pe(None);
+        let stripped = strip_blob(&synthetic_pe(Some(&sample_blob(Layout::current()))), None).unwrap();
+        assert_eq!(stripped.method, StripMethod::SectionRemoved);
+        assert_eq!(stripped.bytes, base);
+        assert!(stripped.warnings.is_empty());
+    }
+
+    #[test]
+    fn pe_strip_after_grow_restores_runtime() {
+        let exe = synthetic_pe(Some(&sample_blob(Layout::current())));
+        let injected = inject_blob(&exe, &grown_blob(5000), None).unwrap();
+        assert_eq!(strip_blob(&injected.bytes, None).unwrap().bytes, synthetic_pe(None));
+    }
+
+    #[test]
+    fn pe_strip_zeroes_when_data_follows() {
+        let mut exe = synthetic_pe(Some(&sample_blob(Layout::current())));
+        exe.extend_from_slice(&[1; 16]);
+        let stripped = strip_blob(&exe, None).unwrap();
+        assert_eq!(stripped.method, StripMethod::Zeroed);
+        assert_eq!(stripped.bytes.len(), exe.len());
+        let Container::PeSection { raw, .. } = &stripped.location.container else {
+            panic!("expected a PE section");
+        };
+        assert!(stripped.bytes[raw.clone()].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn macho_strip_zeroes_section() {
+        let exe = synthetic_macho(&sample_blob(Layout::current()), 0x400);
+        let stripped = strip_blob(&exe, None).unwrap();
+        assert_eq!(stripped.method, StripMethod::Zeroed);
+        assert_eq!(stripped.removed, 0);
+        assert_eq!(&stripped.bytes[..0x100], &exe[..0x100]);
+        assert!(stripped.bytes[0x100..].iter().all(|&b| b == 0));
+    }
+}
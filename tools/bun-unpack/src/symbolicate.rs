@@ -0,0 +1,299 @@
+//! Map stack trace frames from a compiled binary (`/$bunfs/root/cli.js:12:5`)
+//! back to original sources through the embedded sourcemaps.
+//!
+//! **Interaction**: Used by the CLI `symbolicate` subcommand. A `Symbolicator`
+//! is built from a parsed graph or from an unpacked directory's
+//! `manifest.json`; frame paths are resolved to modules with
+//! `name_to_rel_path`, the same normalization `unpack` uses for file names.
+//! Sourcemaps (JSON or serialized) are decoded on first use; one that does not
+//! decode leaves that module's frames unchanged and is reported through
+//! `Symbolicator::take_warnings`.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::graph::ModuleGraph;
+use crate::manifest::{Manifest, MANIFEST_JSON};
+use crate::sourcemap::{decode_mappings, Mapping, SerializedSourceMap, SourceMap};
+use crate::unpack::name_to_rel_path;
+
+/// Virtual roots that frame paths of compiled binaries start with.
+const FRAME_PREFIXES: [&str; 3] = ["/$bunfs/", "B:/~BUN/", "B:\\~BUN\\"];
+
+/// Where a generated position came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OriginalPosition {
+    /// Source name from the sourcemap (with `sourceRoot`).
+    pub source: String,
+    /// 1-based, like stack traces.
+    pub line: u32,
+    /// 1-based, like stack traces.
+    pub column: u32,
+    pub name: Option<String>,
+}
+
+/// A decoded sourcemap: segments grouped by generated line.
+struct DecodedMap {
+    sources: Vec<String>,
+    names: Vec<String>,
+    lines: Vec<Vec<Mapping>>,
+}
+
+impl DecodedMap {
+    /// Decodes raw sourcemap bytes in either form.
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let map = match (SourceMap::parse(bytes), SerializedSourceMap::parse(bytes)) {
+            (Some(map), _) => map,
+            (None, Some(serialized)) => SourceMap::from_serialized(&serialized)?,
+            (None, None) => anyhow::bail!("neither a JSON nor a serialized sourcemap"),
+        };
+        Self::new(map)
+    }
+
+    fn new(map: SourceMap) -> Result<Self> {
+        let mut lines: Vec<Vec<Mapping>> = Vec::new();
+        for m in decode_mappings(&map.mappings)? {
+            let line = m.generated_line as usize;
+            if lines.len() <= line {
+                lines.resize_with(line + 1, Vec::new);
+            }
+            lines[line].push(m);
+        }
+        for segments in &mut lines {
+            segments.sort_by_key(|m| m.generated_column);
+        }
+        let root = map.source_root.clone().unwrap_or_default();
+        let sources = map
+            .sources
+            .iter()
+            .map(|s| {
+                let s = s.as_deref().unwrap_or("");
+                if root.is_empty() || root.ends_with('/') {
+                    format!("{}{}", root, s)
+                } else {
+                    format!("{}/{}", root, s)
+                }
+            })
+            .collect();
+        Ok(Self {
+            sources,
+            names: map.names,
+            lines,
+        })
+    }
+
+    /// Last segment on `line` starting at or before `column` (both 0-based).
+    fn lookup(&self, line: u32, column: u32) -> Option<OriginalPosition> {
+        let segments = self.lines.get(line as usize)?;
+        let i = segments.partition_point(|m| m.generated_column <= column);
+        let m = segments[..i].last()?;
+        Some(OriginalPosition {
+            source: self.sources.get(m.source? as usize)?.clone(),
+            line: m.original_line + 1,
+            column: m.original_column + 1,
+            name: m.name.and_then(|n| self.names.get(n as usize).cloned()),
+        })
+    }
+}
+
+/// Resolves generated positions in modules to original positions.
+pub struct Symbolicator<'a> {
+    /// Module relative path -> raw sourcemap bytes.
+    raw: HashMap<String, Cow<'a, [u8]>>,
+    /// Decoded on first use; `None` if the sourcemap does not decode.
+    decoded: HashMap<String, Option<DecodedMap>>,
+    /// Sourcemaps that failed to decode since the last `take_warnings`.
+    warnings: Vec<String>,
+}
+
+impl<'a> Symbolicator<'a> {
+    /// Uses the sourcemaps embedded in `graph`.
+    pub fn from_graph(graph: &ModuleGraph<'a>) -> Self {
+        let raw = graph
+            .modules
+            .iter()
+            .filter(|m| !m.sourcemap.is_empty())
+            .map(|m| (m.rel_path(), Cow::Borrowed(m.sourcemap)))
+            .collect();
+        Self {
+            raw,
+            decoded: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl Symbolicator<'static> {
+    /// Reads the sourcemaps an unpacked directory lists in its `manifest.json`.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let manifest_path = dir.join(MANIFEST_JSON);
+        let json = fs::read(&manifest_path).with_context(|| format!("read {:?}", manifest_path))?;
+        let manifest: Manifest =
+            serde_json::from_slice(&json).with_context(|| format!("parse {:?}", manifest_path))?;
+        let mut raw = HashMap::new();
+        for m in &manifest.modules {
+            let Some(file) = m.sourcemap.as_ref().and_then(|p| p.file.as_deref()) else {
+                continue;
+            };
+            let path = dir.join(file);
+            let bytes = fs::read(&path).with_context(|| format!("read sourcemap {:?}", path))?;
+            raw.insert(m.rel_path.clone(), Cow::Owned(bytes));
+        }
+        Ok(Self {
+            raw,
+            decoded: HashMap::new(),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+impl Symbolicator<'_> {
+    /// Maps a 1-based `line:column` in the module named `name` (virtual name or
+    /// relative path). `None` if the module has no usable sourcemap or no
+    /// segment covers the position.
+    pub fn lookup(&mut self, name: &str, line: u32, column: u32) -> Option<OriginalPosition> {
+        let rel = name_to_rel_path(name);
+        if !self.decoded.contains_key(&rel) {
+            let map = self.raw.get(&rel).and_then(|bytes| match DecodedMap::from_bytes(bytes) {
+                Ok(map) => Some(map),
+                Err(e) => {
+                    self.warnings.push(format!("{}: sourcemap does not decode, frames left unchanged: {:#}", rel, e));
+                    None
+                }
+            });
+            self.decoded.insert(rel.clone(), map);
+        }
+        self.decoded.get(&rel)?.as_ref()?.lookup(line.checked_sub(1)?, column.saturating_sub(1))
+    }
+
+    /// Sourcemaps that failed to decode since the last call, once per module.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Rewrites every `<virtual path>:line[:column]` frame in `text` to its
+    /// original position. When the sourcemap has a name for it, the function
+    /// name of a V8/JSC style `at name (location)` frame is replaced too.
+    /// Frames that cannot be mapped are left unchanged.
+    pub fn symbolicate_line(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some((start, frame)) = next_frame(rest) {
+            let (before, after) = rest.split_at(start);
+            let Some(pos) = self.lookup(frame.path, frame.line, frame.column) else {
+                out.push_str(before);
+                out.push_str(&after[..frame.len]);
+                rest = &after[frame.len..];
+                continue;
+            };
+            match pos.name.as_deref().and_then(|name| replace_function_name(before, name)) {
+                Some(renamed) => out.push_str(&renamed),
+                None => out.push_str(before),
+            }
+            out.push_str(&format!("{}:{}:{}", pos.source, pos.line, pos.column));
+            rest = &after[frame.len..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// A `<path>:<line>[:<column>]` location found in a trace line.
+struct Frame<'t> {
+    path: &'t str,
+    line: u32,
+    column: u32,
+    /// Byte length of the whole location.
+    len: usize,
+}
+
+/// Finds the next frame location in `text`; returns its byte offset.
+fn next_frame(text: &str) -> Option<(usize, Frame<'_>)> {
+    let mut from = 0;
+    loop {
+        let start = FRAME_PREFIXES
+            .iter()
+            .filter_map(|p| text[from..].find(p).map(|i| from + i))
+            .min()?;
+        // The location runs until whitespace or a delimiter; a Windows-style
+        // prefix includes a drive colon, so split line/column from the end.
+        let token_len = text[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '"' | '\'' | '<' | '>' | ','))
+            .unwrap_or(text.len() - start);
+        let token = &text[start..start + token_len];
+        if let Some(frame) = parse_location(token) {
+            return Some((start, frame));
+        }
+        from = start + 1;
+    }
+}
+
+/// Splits `path:line:column` (or `path:line`) off the end of `token`.
+fn parse_location(token: &str) -> Option<Frame<'_>> {
+    let (rest, last) = token.rsplit_once(':')?;
+    let last = last.parse::<u32>().ok()?;
+    let (path, line, column) = match rest.rsplit_once(':') {
+        Some((path, line)) if line.parse::<u32>().is_ok() => (path, line.parse().ok()?, last),
+        _ => (rest, last, 0),
+    };
+    Some(Frame {
+        path,
+        line,
+        column,
+        len: token.len(),
+    })
+}
+
+/// In `prefix` ending with `at <fn> (`, replaces `<fn>` with `name`.
+fn replace_function_name(prefix: &str, name: &str) -> Option<String> {
+    let head = prefix.strip_suffix('(')?.strip_suffix(' ')?;
+    let at = head.rfind("at ")? + 3;
+    if head[at..].contains(' ') || head[at..].is_empty() {
+        return None;
+    }
+    Some(format!("{}{} (", &head[..at], name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::sample_blob;
+    use crate::layout::Layout;
+    use crate::pack::GraphBuilder;
+
+    #[test]
+    fn maps_frames_through_the_sourcemap() {
+        let blob = sample_blob(Layout::current());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut symbolicator = Symbolicator::from_graph(&graph);
+        assert_eq!(
+            symbolicator.symbolicate_line("    at main (/$bunfs/root/cli.js:1:5)"),
+            "    at main (../src/cli.ts:1:1)"
+        );
+        assert_eq!(symbolicator.lookup("root/lib/util.js", 1, 1), None, "no sourcemap");
+        assert!(symbolicator.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn bad_sourcemap_is_reported_once() {
+        let blob = sample_blob(Layout::current());
+        let graph = ModuleGraph::parse(&blob).unwrap();
+        let mut builder = GraphBuilder::from_graph(&graph);
+        builder.modules[0].sourcemap.data = br#"{"version":3,"sources":["a.ts"],"mappings":"AAAA,AA"}"#.to_vec().into();
+        let blob = builder.build().unwrap();
+        let graph = ModuleGraph::parse(&blob).unwrap();
+
+        let mut symbolicator = Symbolicator::from_graph(&graph);
+        let line = "at /$bunfs/root/cli.js:1:1 and /$bunfs/root/cli.js:1:2";
+        assert_eq!(symbolicator.symbolicate_line(line), line);
+        let warnings = symbolicator.take_warnings();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(warnings[0].starts_with("root/cli.js: sourcemap does not decode"), "{}", warnings[0]);
+        assert!(warnings[0].contains("invalid VLQ mappings at byte 5"), "{}", warnings[0]);
+        assert!(symbolicator.take_warnings().is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+//! Write a parsed module graph to an output directory.
+//!
+//! **Interaction**: Consumer of `ModuleGraph`; used by the CLI's default mode.
+//! Each module's `contents` goes to its virtual path, `sourcemap` to `<path>.map`
+//! and `bytecode` to `<path>.jsc`; `manifest.txt` lists entry point and modules
+//! and `manifest.json` carries the same data in machine-readable form. A module
+//! path starting with one of those names (or `sources/`, `bundled/`) is moved
+//! aside by `module_rel_path`. With
+//! `UnpackOptions::sources`, original sources are recovered into `sources/`;
+//! with `UnpackOptions::bundled`, bundled JS is split into `bundled/`.
+//! A sourcemap in Bun's serialized form is written raw to `<path>.bunmap` and
+//! converted to v3 JSON at `<path>.map`.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::assets::is_asset_with;
+use crate::bundled::{write_bundled, BUNDLED_DIR};
+use crate::graph::{Module, ModuleGraph};
+use crate::manifest::{ExecutableFormat, Manifest, SourceInfo, MANIFEST_JSON};
+use crate::sourcemap::{SerializedSourceMap, SourceMap};
+use crate::sources::{with_variant_suffix, write_sources, SOURCES_DIR};
+
+/// Parses a raw `blob` (source format `blob`) and writes every module to `out_dir`.
+/// Returns the parsed graph.
+pub fn unpack_blob<'a>(blob: &'a [u8], out_dir: &Path) -> Result<ModuleGraph<'a>> {
+    let graph = ModuleGraph::parse(blob)?;
+    let source = SourceInfo {
+        path: None,
+        format: ExecutableFormat::Blob,
+        arch: None,
+        runtime: None,
+    };
+    write_module_graph(&graph, out_dir, source)?;
+    Ok(graph)
+}
+
+/// Writes name/contents/sourcemap/bytecode of each module plus `manifest.txt`
+/// and `manifest.json`. Returns the manifest that was written.
+pub fn write_module_graph(
+    graph: &ModuleGraph<'_>,
+    out_dir: &Path,
+    source: SourceInfo,
+) -> Result<Manifest> {
+    write_selected_modules(graph, out_dir, source, |_| true)
+}
+
+/// Like `write_module_graph`, but only writes modules for which `select`
+/// returns true. `manifest.json` still lists every module; parts of skipped
+/// modules have `file: null`.
+pub fn write_selected_modules(
+    graph: &ModuleGraph<'_>,
+    out_dir: &Path,
+    source: SourceInfo,
+    select: impl Fn(&Module<'_>) -> bool,
+) -> Result<Manifest> {
+    write_modules(graph, out_dir, source, &UnpackOptions::default(), select)
+}
+
+/// Optional outputs beyond the raw module parts.
+#[derive(Clone, Debug, Default)]
+pub struct UnpackOptions {
+    /// Also write original sources from sourcemap `sourcesContent` to `sources/`.
+    pub sources: bool,
+    /// Also split bundled JS modules into their original files under `bundled/`.
+    pub bundled: bool,
+    /// Only write modules that are assets (`is_asset_with` on the manifest entry).
+    pub assets_only: bool,
+}
+
+/// Like `write_selected_modules`, with the extra outputs enabled in `options`.
+pub fn write_modules(
+    graph: &ModuleGraph<'_>,
+    out_dir: &Path,
+    source: SourceInfo,
+    options: &UnpackOptions,
+    select: impl Fn(&Module<'_>) -> bool,
+) -> Result<Manifest> {
+    fs::create_dir_all(out_dir).with_context(|| format!("create out dir {:?}", out_dir))?;
+
+    let mut manifest = Vec::new();
+    // Modules whose serialized sourcemap was written as JSON too.
+    let mut converted = Vec::new();
+    manifest.push(format!(
+        "entry_point_id={} (0-based index into modules)",
+        graph.entry_point_id
+    ));
+    manifest.push(format!("modules_count={}", graph.modules.len()));
+    manifest.push(format!("compile_exec_argv={:?}", graph.compile_exec_argv()));
+    manifest.push(format!(
+        "flags={:#x} {:?}",
+        graph.offsets.flags,
+        graph.offsets.flag_names()
+    ));
+    if let Some(runtime) = &source.runtime {
+        manifest.push(format!("runtime={}", runtime));
+    }
+    manifest.push(String::new());
+    // Built up front so each module's contents are sniffed for an asset once.
+    let json_manifest = Manifest::from_graph(graph, source);
+    let selected: Vec<bool> = graph
+        .modules
+        .iter()
+        .map(|m| select(m) && (!options.assets_only || is_asset_with(m, json_manifest.modules[m.index].asset.as_ref())))
+        .collect();
+    let select = |m: &Module<'_>| selected[m.index];
+
+    for module in &graph.modules {
+        let i = module.index;
+        let rel_path = module_rel_path(&module.rel_path());
+        if rel_path.is_empty() || !select(module) {
+            continue;
+        }
+        let full = out_dir.join(&rel_path);
+        if let Some(p) = full.parent() {
+            fs::create_dir_all(p).with_context(|| format!("create dir {:?}", p))?;
+        }
+
+        let mut has_any = false;
+        if !module.contents.is_empty() {
+            let b = module.contents;
+            let p = full.as_path();
+            fs::write(p, b).with_context(|| format!("write {:?}", p))?;
+            has_any = true;
+            manifest.push(format!("[{}] {} (contents {} bytes)", i, rel_path, b.len()));
+        }
+        if !module.sourcemap.is_empty() {
+            let b = module.sourcemap;
+            let serialized = SerializedSourceMap::parse(b);
+            let map_rel = if serialized.is_some() {
+                serialized_sourcemap_rel_path(&rel_path)
+            } else {
+                sourcemap_rel_path(&rel_path)
+            };
+            let map_full = out_dir.join(&map_rel);
+            if let Some(parent) = map_full.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&map_full, b).with_context(|| format!("write sourcemap {:?}", map_full))?;
+            has_any = true;
+            manifest.push(format!("  -> sourcemap {} ({} bytes)", map_rel, b.len()));
+            if let Some(serialized) = serialized {
+                // Standard tools look for `<path>.map`; give them the v3 JSON form.
+                match SourceMap::from_serialized(&serialized) {
+                    Ok(mut map) => {
+                        map.file = rel_path.rsplit('/').next().map(str::to_string);
+                        let json_rel = sourcemap_rel_path(&rel_path);
+                        let json = serde_json::to_vec(&map).context("serialize sourcemap")?;
+                        fs::write(out_dir.join(&json_rel), &json)
+                            .with_context(|| format!("write sourcemap {:?}", json_rel))?;
+                        manifest.push(format!("  -> sourcemap {} ({} bytes, converted to JSON)", json_rel, json.len()));
+                        converted.push(module.index);
+                    }
+                    Err(e) => manifest.push(format!("  -> sourcemap not converted: {}", e)),
+                }
+            }
+        }
+        if !module.bytecode.is_empty() {
+            let b = module.bytecode;
+            let jsc_rel = bytecode_rel_path(&rel_path);
+            let jsc_full = out_dir.join(&jsc_rel);
+            if let Some(parent) = jsc_full.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&jsc_full, b).with_context(|| format!("write bytecode {:?}", jsc_full))?;
+            has_any = true;
+            manifest.push(format!("  -> bytecode {} ({} bytes, JSC)", jsc_rel, b.len()));
+        }
+        if !has_any {
+            manifest.push(format!("[{}] {} (no contents/sourcemap/bytecode)", i, rel_path));
+        }
+        manifest.push(format!(
+            "  -> loader={} module_format={} encoding={} side={}",
+            module.loader, module.module_format, module.encoding, module.side
+        ));
+        if let Some(asset) = &json_manifest.modules[i].asset {
+            let mut line = format!("  -> asset {}", asset.kind);
+            if !asset.arch.is_empty() {
+                line.push_str(&format!(" ({})", asset.arch.join(", ")));
+            }
+            if !asset.exports.is_empty() {
+                // The full list is in manifest.json; a shared library can export hundreds.
+                let shown = asset.exports.len().min(8);
+                line.push_str(&format!(", exports: {}", asset.exports[..shown].join(", ")));
+                if asset.exports.len() > shown {
+                    line.push_str(&format!(" (+{} more)", asset.exports.len() - shown));
+                }
+            }
+            manifest.push(line);
+        }
+    }
+
+    let sources = if options.sources {
+        write_sources(graph, out_dir, select)?
+    } else {
+        Vec::new()
+    };
+    if !sources.is_empty() {
+        manifest.push(String::new());
+        manifest.push(format!("sources ({} files from sourcesContent):", sources.len()));
+        for s in &sources {
+            manifest.push(format!("  {} ({} bytes, modules {:?})", s.file, s.size, s.modules));
+        }
+    }
+
+    let bundled = if options.bundled {
+        write_bundled(graph, out_dir, select)?
+    } else {
+        Vec::new()
+    };
+    if !bundled.is_empty() {
+        manifest.push(String::new());
+        manifest.push(format!("bundled ({} files split from bundles):", bundled.len()));
+        for b in &bundled {
+            manifest.push(format!(
+                "  [{}] lines {}-{} {} -> {} ({})",
+                b.module,
+                b.start_line,
+                b.end_line,
+                b.path,
+                b.file,
+                b.wrapper.as_str()
+            ));
+        }
+    }
+
+    fs::write(out_dir.join("manifest.txt"), manifest.join("\n"))
+        .context("write manifest.txt")?;
+
+    let mut manifest = json_manifest;
+    manifest.sources = sources;
+    manifest.bundled = bundled;
+    for (entry, module) in manifest.modules.iter_mut().zip(&graph.modules) {
+        if !converted.contains(&module.index) {
+            entry.sourcemap_json = None;
+        }
+        if !select(module) {
+            for part in [&mut entry.contents, &mut entry.sourcemap, &mut entry.bytecode]
+                .into_iter()
+                .flatten()
+            {
+                part.file = None;
+            }
+        }
+    }
+    let json = serde_json::to_vec_pretty(&manifest).context("serialize manifest.json")?;
+    fs::write(out_dir.join(MANIFEST_JSON), json).context("write manifest.json")?;
+    Ok(manifest)
+}
+
+/// Names `write_modules` generates at the top of the output directory.
+const GENERATED_NAMES: [&str; 4] = ["manifest.txt", MANIFEST_JSON, SOURCES_DIR, BUNDLED_DIR];
+
+/// Output path of a module's contents: `rel_path`, unless its first component
+/// is a generated name (`manifest.json`, `sources`, ...), which then gets a
+/// `~1` suffix: `manifest~1.json`, `sources~1/a.ts`. Sourcemap and bytecode
+/// paths are derived from it.
+pub fn module_rel_path(rel_path: &str) -> String {
+    let (first, rest) = match rel_path.split_once('/') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (rel_path, None),
+    };
+    if !GENERATED_NAMES.contains(&first) {
+        return rel_path.to_string();
+    }
+    let first = with_variant_suffix(first, 1);
+    match rest {
+        Some(rest) => format!("{}/{}", first, rest),
+        None => first,
+    }
+}
+
+/// Output path of a module's sourcemap: `<rel_path>.map`.
+pub fn sourcemap_rel_path(rel_path: &str) -> String {
+    format!("{}.map", rel_path)
+}
+
+/// Output path of a sourcemap in Bun's serialized (non-JSON) form: `<rel_path>.bunmap`.
+/// Its JSON conversion goes to `sourcemap_rel_path`.
+pub fn serialized_sourcemap_rel_path(rel_path: &str) -> String {
+    format!("{}.bunmap", rel_path)
+}
+
+/// Output path of a module's bytecode: `<rel_path>.jsc` unless it already ends in `.jsc`.
+pub fn bytecode_rel_path(rel_path: &str) -> String {
+    if rel_path.ends_with(".jsc") {
+        rel_path.to_string()
+    } else {
+        format!("{}.jsc", rel_path)
+    }
+}
+
+/// Maps a virtual module name to a safe relative path. Strips Bun's virtual
+/// root prefixes; returns an empty string for absolute or `..` paths and for
+/// names containing NUL (not representable as a file name).
+pub fn name_to_rel_path(name: &str) -> String {
+    let s = name
+        .trim_start_matches("file:///")
+        .trim_start_matches("B:\\~BUN\\")
+        .trim_start_matches("B:/~BUN/")
+        .trim_start_matches("/$bunfs/");
+    let s = s.replace('\\', "/");
+    if s.contains("..") || s.starts_with('/') || s.contains('\0') {
+        return String::new();
+    }
+    if s.is_empty() {
+        return String::new();
+    }
+    s
+}
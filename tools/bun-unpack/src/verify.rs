@@ -0,0 +1,379 @@
+//! Integrity check of a blob without extracting anything: every StringPointer,
+//! the entry point and module names, plus a coverage map of the blob.
+//!
+//! **Interaction**: Used by the CLI `verify` subcommand. Unlike
+//! `ModuleGraph::parse`, which stops at the first failed invariant, and
+//! `parse_with_layout`, which turns a bad pointer into an empty slice, this
+//! reports every problem. The coverage map labels each byte range with what
+//! references it; unreferenced ranges holding non-zero bytes are flagged, since
+//! Bun only leaves NUL terminators and alignment padding between parts.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::error::ParseError;
+use crate::graph::{ModuleGraph, StringPointer, BUN_TRAILER};
+use crate::layout::{Layout, LAYOUTS};
+use crate::unpack::name_to_rel_path;
+
+/// Result of verifying one blob.
+#[derive(Clone, Debug, Serialize)]
+pub struct VerifyReport {
+    /// Layout the blob was checked with.
+    pub layout: &'static str,
+    /// Why layout detection failed, if the layout was picked leniently.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection_error: Option<String>,
+    pub blob_len: usize,
+    pub modules: usize,
+    /// Every byte range of the blob in order, referenced or not.
+    pub coverage: Vec<Region>,
+    pub issues: Vec<Issue>,
+}
+
+/// One byte range of the coverage map.
+#[derive(Clone, Debug, Serialize)]
+pub struct Region {
+    pub range: Range<usize>,
+    /// What references it (`#3 contents`, `module table`, ...); `None` if nothing does.
+    pub label: Option<String>,
+    /// For unreferenced ranges: every byte is zero (terminators, padding).
+    pub zero: bool,
+}
+
+/// One integrity problem.
+#[derive(Clone, Debug, Serialize)]
+pub struct Issue {
+    /// Module index, if the problem belongs to one module.
+    pub module: Option<usize>,
+    pub message: String,
+}
+
+impl VerifyReport {
+    /// Bytes covered by at least one labeled region; overlaps count once.
+    pub fn referenced_bytes(&self) -> usize {
+        // Labeled regions are sorted by start; add what each adds past the furthest end so far.
+        let mut covered = 0;
+        let mut total = 0;
+        for r in self.coverage.iter().filter(|r| r.label.is_some()) {
+            total += r.range.end.saturating_sub(r.range.start.max(covered));
+            covered = covered.max(r.range.end);
+        }
+        total
+    }
+
+    /// Unreferenced ranges that hold non-zero bytes.
+    pub fn unaccounted(&self) -> impl Iterator<Item = &Region> {
+        self.coverage.iter().filter(|r| r.label.is_none() && !r.zero)
+    }
+}
+
+/// Verifies `blob` with `forced`, else the detected layout, else the first
+/// layout whose structure (trailer, Offsets, module table) parses, so a blob
+/// failing the invariants is still checked in full.
+pub fn verify_blob(blob: &[u8], forced: Option<&'static Layout>) -> Result<VerifyReport, ParseError> {
+    let (graph, detection_error) = match forced {
+        Some(layout) => (ModuleGraph::parse_with_layout(blob, layout)?, None),
+        None => match ModuleGraph::parse(blob) {
+            Ok(graph) => (graph, None),
+            Err(error) => {
+                let graph = LAYOUTS
+                    .iter()
+                    .find_map(|layout| ModuleGraph::parse_with_layout(blob, layout).ok())
+                    .ok_or_else(|| error.clone())?;
+                (graph, Some(error.to_string()))
+            }
+        },
+    };
+    let mut report = verify_graph(&graph);
+    report.detection_error = detection_error;
+    Ok(report)
+}
+
+/// Checks every pointer of `graph` and maps the coverage of `graph.blob`.
+pub fn verify_graph(graph: &ModuleGraph<'_>) -> VerifyReport {
+    let blob = graph.blob;
+    let data_end = graph.offsets.byte_count as usize;
+    let offsets_start = blob.len() - BUN_TRAILER.len() - graph.layout.offsets_size;
+    let mut issues = Vec::new();
+    let mut regions: Vec<(Range<usize>, String)> = Vec::new();
+
+    let mut pointer = |module: Option<usize>, label: String, p: StringPointer| {
+        if p.length == 0 {
+            return;
+        }
+        if p.end() > data_end as u64 {
+            issues.push(Issue {
+                module,
+                message: format!(
+                    "{} ({:#x}, {}) ends past the data ({:#x} bytes)",
+                    label, p.offset, p.length, data_end
+                ),
+            });
+            return;
+        }
+        regions.push((p.offset as usize..p.end() as usize, label));
+    };
+    for m in &graph.modules {
+        let p = &m.pointers;
+        for (field, ptr) in [("name", p.name), ("contents", p.contents), ("sourcemap", p.sourcemap), ("bytecode", p.bytecode)] {
+            pointer(Some(m.index), format!("#{} {}", m.index, field), ptr);
+        }
+    }
+    pointer(
+        None,
+        "exec argv".to_string(),
+        StringPointer {
+            offset: graph.offsets.compile_exec_argv_offset,
+            length: graph.offsets.compile_exec_argv_length,
+        },
+    );
+    let table = graph.offsets.modules_ptr_offset as usize;
+    regions.push((table..table + graph.offsets.modules_ptr_length as usize, "module table".to_string()));
+    regions.push((offsets_start..blob.len() - BUN_TRAILER.len(), "offsets".to_string()));
+    regions.push((blob.len() - BUN_TRAILER.len()..blob.len(), "trailer".to_string()));
+
+    for m in &graph.modules {
+        if m.name.is_empty() {
+            issues.push(Issue {
+                module: Some(m.index),
+                message: "empty name".to_string(),
+            });
+        } else if name_to_rel_path(&m.name_str()).is_empty() {
+            issues.push(Issue {
+                module: Some(m.index),
+                message: format!("name {:?} is rejected as an output path", m.name_str()),
+            });
+        }
+    }
+    if graph.entry_point_id as usize >= graph.modules.len() {
+        issues.push(Issue {
+            module: None,
+            message: format!(
+                "entry_point_id {} is past the last module ({} modules)",
+                graph.entry_point_id,
+                graph.modules.len()
+            ),
+        });
+    }
+    if data_end < offsets_start {
+        issues.push(Issue {
+            module: None,
+            message: format!(
+                "byte_count {:#x} leaves {} bytes before Offsets outside the data",
+                data_end,
+                offsets_start - data_end
+            ),
+        });
+    }
+
+    regions.sort_by_key(|(r, _)| (r.start, r.end));
+    // Sweep: compare each region with the one reaching furthest so far.
+    let mut furthest: Option<&(Range<usize>, String)> = None;
+    for region in &regions {
+        if let Some(prev) = furthest {
+            if region.0.start < prev.0.end {
+                issues.push(Issue {
+                    module: None,
+                    message: format!(
+                        "{} {:#x}..{:#x} overlaps {} {:#x}..{:#x}",
+                        region.1, region.0.start, region.0.end, prev.1, prev.0.start, prev.0.end
+                    ),
+                });
+            }
+        }
+        if furthest.is_none_or(|prev| region.0.end > prev.0.end) {
+            furthest = Some(region);
+        }
+    }
+
+    let coverage = coverage_map(blob, &regions);
+    for gap in coverage.iter().filter(|r| r.label.is_none() && !r.zero) {
+        issues.push(Issue {
+            module: None,
+            message: format!(
+                "{} unreferenced non-zero bytes at {:#x}..{:#x}",
+                gap.range.len(),
+                gap.range.start,
+                gap.range.end
+            ),
+        });
+    }
+
+    VerifyReport {
+        layout: graph.layout.name,
+        detection_error: None,
+        blob_len: blob.len(),
+        modules: graph.modules.len(),
+        coverage,
+        issues,
+    }
+}
+
+/// Labeled regions (sorted by start) plus the gaps between them, covering the
+/// whole blob. Overlapping regions are listed as they are; gaps start after the
+/// furthest end seen so far.
+fn coverage_map(blob: &[u8], regions: &[(Range<usize>, String)]) -> Vec<Region> {
+    let mut map = Vec::with_capacity(regions.len() * 2 + 1);
+    let mut covered = 0;
+    let gap = |map: &mut Vec<Region>, range: Range<usize>| {
+        if !range.is_empty() {
+            let zero = blob[range.clone()].iter().all(|&b| b == 0);
+            map.push(Region { range, label: None, zero });
+        }
+    };
+    for (range, label) in regions {
+        gap(&mut map, covered..range.start.max(covered));
+        map.push(Region {
+            range: range.clone(),
+            label: Some(label.clone()),
+            zero: false,
+        });
+        covered = covered.max(range.end);
+    }
+    gap(&mut map, covered..blob.len());
+    map
+}
+
+/// Formats `report`: a summary, the coverage map if `full_map`, then the issues
+/// (which include every unreferenced non-zero range).
+pub fn format_verify_report(report: &VerifyReport, full_map: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(e) = &report.detection_error {
+        lines.push(format!("layout detection failed ({}); checked as {}", e, report.layout));
+    }
+    let unreferenced: usize = report.coverage.iter().filter(|r| r.label.is_none()).map(|r| r.range.len()).sum();
+    let unaccounted: usize = report.unaccounted().map(|r| r.range.len()).sum();
+    lines.push(format!(
+        "layout {}, {} modules, {} bytes: {} referenced, {} zero padding, {} unaccounted",
+        report.layout,
+        report.modules,
+        report.blob_len,
+        report.referenced_bytes(),
+        unreferenced - unaccounted,
+        unaccounted
+    ));
+    if full_map {
+        for region in &report.coverage {
+            let label = match (&region.label, region.zero) {
+                (Some(label), _) => label.as_str(),
+                (None, true) => "(padding)",
+                (None, false) => "(unreferenced)",
+            };
+            lines.push(format!(
+                "  {:>10x}..{:<10x} {:>10}  {}",
+                region.range.start,
+                region.range.end,
+                region.range.len(),
+                label
+            ));
+        }
+    }
+    for issue in &report.issues {
+        match issue.module {
+            Some(i) => lines.push(format!("! [{}] {}", i, issue.message)),
+            None => lines.push(format!("! {}", issue.message)),
+        }
+    }
+    lines.push(format!("{} issues", report.issues.len()));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::{sample_blob, with_offsets, with_pointer};
+
+    fn v3() -> &'static Layout {
+        Layout::by_name("v3").unwrap()
+    }
+
+    fn messages(report: &VerifyReport) -> Vec<(Option<usize>, &str)> {
+        report.issues.iter().map(|i| (i.module, i.message.as_str())).collect()
+    }
+
+    /// The coverage map tiles the blob and every byte is referenced or padding.
+    fn assert_tiles(report: &VerifyReport) {
+        let unreferenced: usize = report.coverage.iter().filter(|r| r.label.is_none()).map(|r| r.range.len()).sum();
+        assert_eq!(report.referenced_bytes() + unreferenced, report.blob_len);
+        assert_eq!(report.coverage.last().unwrap().range.end, report.blob_len);
+    }
+
+    #[test]
+    fn clean_blob_is_fully_accounted_for() {
+        for layout in LAYOUTS {
+            let blob = sample_blob(layout);
+            let report = verify_blob(&blob, None).unwrap();
+            assert_eq!(report.layout, layout.name);
+            assert!(report.issues.is_empty(), "{}: {:?}", layout.name, report.issues);
+            assert_eq!(report.unaccounted().count(), 0);
+            assert_tiles(&report);
+            let first = &report.coverage[0];
+            assert_eq!((first.range.clone(), first.label.as_deref()), (0..19, Some("#0 name")));
+            // The NUL after the name is padding.
+            assert_eq!((report.coverage[1].range.clone(), report.coverage[1].zero), (19..20, true));
+            let trailer = report.coverage.last().unwrap();
+            assert_eq!(trailer.range, blob.len() - BUN_TRAILER.len()..blob.len());
+            assert_eq!(trailer.label.as_deref(), Some("trailer"));
+        }
+    }
+
+    #[test]
+    fn flags_out_of_range_pointer() {
+        let blob = with_pointer(v3(), 1, 1, StringPointer { offset: 0x10000, length: 4 });
+        let report = verify_blob(&blob, None).unwrap();
+        assert!(report.detection_error.is_some());
+        let issues = messages(&report);
+        assert_eq!(issues[0].0, Some(1));
+        assert!(issues[0].1.starts_with("#1 contents (0x10000, 4) ends past the data"), "{:?}", issues);
+        // The contents it used to point at (after the 128-aligned bytecode) are
+        // now unreferenced, merged with the NULs on either side.
+        assert_eq!(issues[1], (None, "22 unreferenced non-zero bytes at 0xc0..0xd6"));
+        assert_eq!(issues.len(), 2);
+        assert_tiles(&report);
+    }
+
+    #[test]
+    fn flags_overlap_and_counts_it_once() {
+        // Module 1's contents point at module 0's.
+        let blob = with_pointer(v3(), 1, 1, StringPointer { offset: 20, length: 16 });
+        let report = verify_blob(&blob, Some(v3())).unwrap();
+        let issues = messages(&report);
+        assert!(issues.iter().any(|(_, m)| *m == "#1 contents 0x14..0x24 overlaps #0 contents 0x14..0x24"), "{:?}", issues);
+        let labeled: Vec<_> = report.coverage.iter().filter(|r| r.range == (20..36)).map(|r| r.label.as_deref()).collect();
+        assert_eq!(labeled, [Some("#0 contents"), Some("#1 contents")]);
+        assert_tiles(&report);
+        let clean = verify_blob(&sample_blob(v3()), None).unwrap();
+        // The old contents of module 1 (20 bytes) are no longer referenced.
+        assert_eq!(report.referenced_bytes(), clean.referenced_bytes() - 20);
+    }
+
+    #[test]
+    fn flags_entry_point_past_the_end() {
+        let blob = with_offsets(v3(), |off| off.entry_point_id = 5);
+        let report = verify_blob(&blob, None).unwrap();
+        assert_eq!(messages(&report), [(None, "entry_point_id 5 is past the last module (2 modules)")]);
+    }
+
+    #[test]
+    fn flags_rejected_name() {
+        let mut blob = sample_blob(v3());
+        blob[..19].copy_from_slice(b"B:/~BUN/root/../.js");
+        let report = verify_blob(&blob, None).unwrap();
+        assert_eq!(messages(&report), [(Some(0), "name \"B:/~BUN/root/../.js\" is rejected as an output path")]);
+    }
+
+    #[test]
+    fn flags_unreferenced_bytes() {
+        let mut blob = sample_blob(v3());
+        blob[19] = 0xff;
+        let report = verify_blob(&blob, None).unwrap();
+        assert_eq!(messages(&report), [(None, "1 unreferenced non-zero bytes at 0x13..0x14")]);
+        let gap: Vec<_> = report.unaccounted().map(|r| (r.range.start, r.range.end)).collect();
+        assert_eq!(gap, [(19, 20)]);
+        let lines = format_verify_report(&report, true);
+        assert!(lines.iter().any(|l| l.trim_end().ends_with("1  (unreferenced)")), "{:?}", lines);
+        assert_eq!(lines.last().unwrap(), "1 issues");
+    }
+}